@@ -0,0 +1,30 @@
+use std::time::Instant;
+
+use advent_of_code_2022::day13::{cmp_raw, IntList};
+
+fn main() {
+    let packets: Vec<String> = IntList::generate(200_000, 13).iter().map(IntList::to_string).collect();
+    let pairs = packets.len() / 2;
+    let bytes: usize = packets.iter().map(String::len).sum();
+
+    let start = Instant::now();
+    let trees: Vec<IntList> = packets.iter().map(|packet| packet.parse().unwrap()).collect();
+    let parsed = start.elapsed();
+    let ordered = trees.chunks(2).filter(|pair| pair[0] < pair[1]).count();
+    let elapsed = start.elapsed();
+
+    println!(
+        "tree: {} of {} pairs in order in {:?}, {:?} of it parsing {} KiB",
+        ordered,
+        pairs,
+        elapsed,
+        parsed,
+        bytes >> 10,
+    );
+
+    let start = Instant::now();
+    let ordered = packets.chunks(2).filter(|pair| cmp_raw(pair[0].as_bytes(), pair[1].as_bytes()).is_lt()).count();
+    let elapsed = start.elapsed();
+
+    println!("raw: {} of {} pairs in order in {:?}", ordered, pairs, elapsed);
+}
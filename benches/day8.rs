@@ -0,0 +1,33 @@
+use std::time::Instant;
+
+use advent_of_code_2022::day8::{best_tree, scenic_scores, visibility, Forest};
+
+fn main() {
+    let forest = Forest::generate(10_000, 10_000, 8);
+
+    let start = Instant::now();
+    let visible = visibility(&forest);
+    let elapsed = start.elapsed();
+
+    println!(
+        "visibility: {} visible in {:?}, using {} MiB of heights and {} MiB of bitset",
+        visible.count(),
+        elapsed,
+        (forest.width() * forest.height()) >> 20,
+        visible.bytes() >> 20,
+    );
+
+    // Scores take 8 bytes a tree, so 10,000 x 10,000 would need 800 MB
+    let forest = Forest::generate(2_000, 2_000, 8);
+
+    let start = Instant::now();
+    let scores = scenic_scores(&forest);
+    let elapsed = start.elapsed();
+
+    println!(
+        "scenic_scores: best score {} in {:?}, using {} MiB of scores",
+        best_tree(&forest, &scores).1,
+        elapsed,
+        (scores.len() * 8) >> 20,
+    );
+}
@@ -0,0 +1,161 @@
+use std::fmt;
+
+/// An arbitrary-precision unsigned integer, stored as little-endian 32-bit limbs with no
+/// trailing zero limbs.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn bits(&self) -> usize {
+        match self.limbs.last() {
+            Some(last) => self.limbs.len() * 32 - last.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    fn normalize(mut self) -> Self {
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        self
+    }
+
+    pub fn add_u64(&self, n: u64) -> Self {
+        let mut limbs = self.limbs.clone();
+        let mut carry = n as u128;
+
+        for limb in limbs.iter_mut() {
+            if carry == 0 {
+                break
+            }
+            carry += *limb as u128;
+            *limb = carry as u32;
+            carry >>= 32;
+        }
+
+        while carry > 0 {
+            limbs.push(carry as u32);
+            carry >>= 32;
+        }
+
+        Self { limbs }.normalize()
+    }
+
+    pub fn mul(&self, other: &Self) -> Self {
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let sum = limbs[i + j] as u64 + a as u64 * b as u64 + carry;
+                limbs[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+
+        Self { limbs }.normalize()
+    }
+
+    /// Divides by `divisor`, returning the quotient and remainder.
+    pub fn div_rem_u64(&self, divisor: u64) -> (Self, u64) {
+        assert_ne!(divisor, 0, "division by zero");
+
+        let mut limbs = vec![0u32; self.limbs.len()];
+        let mut rem = 0u128;
+
+        for (i, &limb) in self.limbs.iter().enumerate().rev() {
+            let acc = rem << 32 | limb as u128;
+            limbs[i] = (acc / divisor as u128) as u32;
+            rem = acc % divisor as u128;
+        }
+
+        (Self { limbs }.normalize(), rem as u64)
+    }
+}
+
+impl From<u64> for BigUint {
+    fn from(n: u64) -> Self {
+        Self { limbs: vec![n as u32, (n >> 32) as u32] }.normalize()
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const CHUNK: u64 = 1_000_000_000;
+
+        let mut chunks = Vec::new();
+        let mut n = self.clone();
+
+        while !n.is_zero() {
+            let (quot, rem) = n.div_rem_u64(CHUNK);
+            chunks.push(rem);
+            n = quot;
+        }
+
+        match chunks.split_last() {
+            None => write!(f, "0"),
+            Some((first, rest)) => {
+                write!(f, "{}", first)?;
+                for chunk in rest.iter().rev() {
+                    write!(f, "{:09}", chunk)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_carries_across_limbs() {
+        assert_eq!(BigUint::from(u32::MAX as u64).add_u64(1), BigUint::from(1 << 32));
+        assert_eq!(BigUint::from(u64::MAX).add_u64(u64::MAX).to_string(), (u64::MAX as u128 * 2).to_string());
+        assert_eq!(BigUint::default().add_u64(0), BigUint::default());
+        assert_eq!(BigUint::from(u64::MAX).add_u64(1).bits(), 65);
+    }
+
+    #[test]
+    fn mul_matches_u128() {
+        let values = [0, 1, 7, u32::MAX as u64, 1 << 32, 1_000_000_007, u64::MAX];
+
+        for &a in &values {
+            for &b in &values {
+                let product = BigUint::from(a).mul(&BigUint::from(b));
+                assert_eq!(product.to_string(), (a as u128 * b as u128).to_string(), "{} * {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn div_rem_matches_u128() {
+        let n = BigUint::from(u64::MAX).mul(&BigUint::from(0xdead_beef));
+        let wide = u64::MAX as u128 * 0xdead_beef;
+
+        for divisor in [1, 3, 10, 1 << 32, u64::MAX] {
+            let (quot, rem) = n.div_rem_u64(divisor);
+            assert_eq!((quot.to_string(), rem as u128), ((wide / divisor as u128).to_string(), wide % divisor as u128));
+        }
+    }
+
+    #[test]
+    fn display_pads_inner_chunks() {
+        assert_eq!(BigUint::default().to_string(), "0");
+        assert_eq!(BigUint::from(1_000_000_000).to_string(), "1000000000");
+        assert_eq!(BigUint::from(1_000_000_000_000_000_007).to_string(), "1000000000000000007");
+
+        let two_64 = BigUint::from(u64::MAX).add_u64(1);
+        assert_eq!(two_64.mul(&two_64).to_string(), "340282366920938463463374607431768211456");
+    }
+}
@@ -1,34 +1,229 @@
-use std::{num::ParseIntError, collections::BinaryHeap, cmp::Reverse};
+use std::{collections::BinaryHeap, cmp::Reverse, fmt};
 
-use aoc_runner_derive::{aoc_generator, aoc};
+use aoc_runner_derive::{aoc, aoc_generator};
+use chumsky::prelude::*;
 
-#[aoc_generator(day1)]
-fn parse(data: &str) -> Result<Vec<Vec<u32>>, ParseIntError> {
-    data.split("\n\n")
-        .map(|group| group.lines().map(str::parse).collect())
+use crate::parse::{normalize, num, input_name, parse_input, ParseFailure};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Elf {
+    /// Counting from 1, in the order the elves appear.
+    pub index: usize,
+    pub total: u32,
+}
+
+/// The `n` elves carrying the most, most first. Ties go to the elf that comes first.
+pub fn top_n(elves: impl IntoIterator<Item=Elf>, n: usize) -> Vec<Elf> {
+    let mut heap = BinaryHeap::new();
+
+    for elf in elves {
+        heap.push(Reverse((elf.total, Reverse(elf.index))));
+
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse((total, Reverse(index)))| Elf { index, total })
         .collect()
 }
 
+#[derive(Clone, Debug)]
+pub struct Stats {
+    pub min: Elf,
+    pub max: Elf,
+    pub median: f64,
+    pub mean: f64,
+}
+
+impl Stats {
+    pub fn new(elves: &[Elf]) -> Option<Self> {
+        if elves.is_empty() {
+            return None
+        }
+
+        let mut totals: Vec<u32> = elves.iter().map(|elf| elf.total).collect();
+        totals.sort_unstable();
+
+        let mid = totals.len() / 2;
+        let median = if totals.len() % 2 == 1 {
+            totals[mid] as f64
+        } else {
+            (totals[mid - 1] as f64 + totals[mid] as f64) / 2.0
+        };
+
+        Some(Self {
+            min: *elves.iter().min_by_key(|elf| elf.total)?,
+            max: top_n(elves.iter().copied(), 1)[0],
+            median,
+            mean: totals.iter().map(|&t| t as f64).sum::<f64>() / totals.len() as f64,
+        })
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "min {} (elf {}), max {} (elf {}), median {:.1}, mean {:.1}",
+            self.min.total, self.min.index, self.max.total, self.max.index, self.median, self.mean
+        )
+    }
+}
+
+/// Buckets the elves' totals into `buckets` equal ranges, with bars scaled to fit `width`.
+pub fn histogram(elves: &[Elf], buckets: usize, width: usize) -> String {
+    let (Some(min), Some(max)) = (elves.iter().map(|e| e.total).min(), elves.iter().map(|e| e.total).max()) else {
+        return String::new()
+    };
+
+    let size = ((max - min) as usize / buckets.max(1)) + 1;
+    let mut counts = vec![0; buckets.max(1)];
+    for elf in elves {
+        counts[(elf.total - min) as usize / size] += 1;
+    }
+
+    let most = counts.iter().copied().max().unwrap_or(1);
+    let label = (max as usize).to_string().len();
+
+    counts.iter().enumerate().map(|(i, &count)| {
+        let low = min as usize + i * size;
+        format!(
+            "{:>label$}-{:>label$} | {} {}\n",
+            low,
+            low + size - 1,
+            "#".repeat(count * width / most),
+            count,
+        )
+    }).collect()
+}
+
+/// Elves separated by one or more blank lines, each carrying at least one item. Each elf's
+/// items are summed as they are read.
+fn inventory() -> impl Parser<char, Vec<Elf>, Error = Simple<char>> {
+    let elf = num::<u32>()
+        .separated_by(just('\n'))
+        .at_least(1)
+        .try_map(|items, span| {
+            items.into_iter()
+                .try_fold(0u32, u32::checked_add)
+                .ok_or_else(|| Simple::custom(span, "these calories add up to too many to count"))
+        });
+
+    elf.separated_by(just('\n').repeated().at_least(2))
+        .then_ignore(end())
+        .map(|totals| totals.into_iter().enumerate().map(|(i, total)| Elf { index: i + 1, total }).collect())
+}
+
+#[aoc_generator(day1)]
+fn parse(data: &str) -> Result<Vec<Elf>, ParseFailure> {
+    let data = normalize(data);
+
+    parse_input(inventory(), &data, &input_name(1))
+}
+
 #[aoc(day1, part1)]
-fn part1(data: &Vec<Vec<u32>>) -> u32 {
-    top_n_sum::<1>(data)
+fn part1(data: &[Elf]) -> u32 {
+    top_n(data.iter().copied(), 1).iter().map(|elf| elf.total).sum()
 }
 
 #[aoc(day1, part2)]
-fn part2(data: &Vec<Vec<u32>>) -> u32 {
-    top_n_sum::<3>(data)
+fn part2(data: &[Elf]) -> u32 {
+    top_n(data.iter().copied(), 3).iter().map(|elf| elf.total).sum()
 }
 
-fn top_n_sum<const N: usize>(data: &Vec<Vec<u32>>) -> u32 {
-    data.iter().map(|group| group.iter().sum())
-        .fold(BinaryHeap::new(), |mut heap, n: u32| {
-            heap.push(Reverse(n));
+#[aoc(day1, part2, Stats)]
+fn part2_stats(data: &[Elf]) -> String {
+    let top: Vec<String> = top_n(data.iter().copied(), 3).iter()
+        .map(|elf| format!("elf {} with {}", elf.index, elf.total))
+        .collect();
 
-            if heap.len() > N {
-                heap.pop();
-            }
+    format!(
+        "{}\n{}\n{}",
+        top.join(", "),
+        Stats::new(data).map_or(String::new(), |stats| stats.to_string()),
+        histogram(data, 10, 40),
+    )
+}
 
-            heap
-        })
-        .iter().map(|r| r.0).sum()
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "\
+1000
+2000
+3000
+
+4000
+
+5000
+6000
+
+7000
+8000
+9000
+
+10000";
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((part1(&parsed), part2(&parsed)), (24000, 45000), "{:?}", data);
+        }
+    }
+
+    fn elves(totals: &[u32]) -> Vec<Elf> {
+        totals.iter().enumerate().map(|(i, &total)| Elf { index: i + 1, total }).collect()
+    }
+
+    #[test]
+    fn top_n_takes_any_n() {
+        let elves = elves(&[3, 9, 1, 7]);
+
+        assert_eq!(top_n(elves.clone(), 0), []);
+        assert_eq!(top_n(elves.clone(), 2), [Elf { index: 2, total: 9 }, Elf { index: 4, total: 7 }]);
+        assert_eq!(top_n(elves.clone(), 10).iter().map(|elf| elf.index).collect::<Vec<_>>(), [2, 4, 1, 3]);
+    }
+
+    #[test]
+    fn ties_go_to_the_first_elf() {
+        let elves = elves(&[5, 8, 5, 8, 5]);
+
+        assert_eq!(top_n(elves.clone(), 1), [Elf { index: 2, total: 8 }]);
+        assert_eq!(top_n(elves.clone(), 3).iter().map(|elf| elf.index).collect::<Vec<_>>(), [2, 4, 1]);
+        assert_eq!(Stats::new(&elves).unwrap().min, Elf { index: 1, total: 5 });
+    }
+
+    #[test]
+    fn stats_take_the_middle_or_middle_two() {
+        let odd = Stats::new(&elves(&[5, 1, 3])).unwrap();
+        assert_eq!((odd.median, odd.mean), (3.0, 3.0));
+        assert_eq!((odd.min.index, odd.max.index), (2, 1));
+
+        let even = Stats::new(&elves(&[4, 1, 3, 2])).unwrap();
+        assert_eq!((even.median, even.mean), (2.5, 2.5));
+        assert_eq!(even.to_string(), "min 1 (elf 2), max 4 (elf 1), median 2.5, mean 2.5");
+
+        assert!(Stats::new(&[]).is_none());
+    }
+
+    #[test]
+    fn histogram_buckets_equal_ranges() {
+        assert_eq!(histogram(&elves(&[1, 2, 3, 10]), 3, 6), " 1- 4 | ###### 3\n 5- 8 |  0\n 9-12 | ## 1\n");
+        assert_eq!(histogram(&elves(&[7, 7]), 2, 4), "7-7 | #### 2\n8-8 |  0\n");
+        assert_eq!(histogram(&[], 3, 6), "");
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("1000\n2x00\n\n3000"), &[("Unexpected token, expected one of: end of input, \\n", 6..7)]);
+        assert_fails(parse("1000\n99999999999"), &[("99999999999 is out of range", 5..16)]);
+        assert_fails(parse("4000000000\n4000000000\n\n1"), &[("these calories add up to too many to count", 0..21)]);
+    }
+}
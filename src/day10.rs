@@ -1,108 +1,538 @@
+use std::{collections::HashSet, fmt, ops::RangeInclusive, str::FromStr};
+
 use aoc_runner_derive::{aoc, aoc_generator};
 
-use parse_display::{FromStr, Display, ParseError};
+use chumsky::prelude::*;
+use parse_display::{FromStr, Display};
+
+use crate::parse::{lines, normalize, input_name, parse_input, ParseFailure};
 
-#[derive(Display, FromStr, Clone, Copy)]
-enum Instruction {
+/// The `display` attribute is both how an instruction is written and how it is parsed, and
+/// [`Instruction::run`] is the only other place a variant needs handling.
+#[derive(Display, FromStr, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
     #[display("noop")]
     Noop,
     #[display("addx {0}")]
     Addx(i32)
 }
 
+impl Instruction {
+    /// How many cycles the instruction takes, and the value of X once it completes.
+    fn run(&self, x: i32) -> (usize, i32) {
+        match *self {
+            Instruction::Noop => (1, x),
+            Instruction::Addx(add) => (2, x + add),
+        }
+    }
+
+    pub fn cycles(&self) -> usize {
+        self.run(0).0
+    }
+}
+
+fn instructions() -> impl Parser<char, Vec<Instruction>, Error = Simple<char>> {
+    let instruction = filter(|&c| c != '\n').repeated().collect::<String>()
+        .try_map(|line, span| line.parse().map_err(|_| Simple::custom(span, format!("unknown instruction {:?}", line))));
+
+    lines(instruction)
+}
+
 #[aoc_generator(day10)]
-fn parse(data: &str) -> Result<Vec<Instruction>, ParseError> {
-    data.lines().map(str::parse).collect()
+fn parse(data: &str) -> Result<Vec<Instruction>, ParseFailure> {
+    let data = normalize(data);
+
+    parse_input(instructions(), &data, &input_name(10))
 }
 
-struct Cpu<'a> {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Trace {
+    pub cycle: usize,
+    pub pc: usize,
+    pub x: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Cycle(usize),
+    Breakpoint(usize),
+    Halted,
+}
+
+pub struct Cpu<'a> {
     program: &'a [Instruction],
-    adding: Option<i32>,
+    breakpoints: HashSet<usize>,
+    /// The breakpoint execution last stopped at, until execution moves past it.
+    paused_at: Option<usize>,
+    trace: Option<Vec<Trace>>,
     cycle: usize,
+    busy: usize,
     pc: usize,
     x: i32,
 }
 
 impl <'a> Cpu<'a> {
-    fn new(program: &'a [Instruction]) -> Cpu {
+    pub fn new(program: &'a [Instruction]) -> Cpu<'a> {
         Cpu {
             program,
+            breakpoints: HashSet::new(),
+            paused_at: None,
+            trace: None,
             cycle: 1,
-            adding: None,
+            busy: 0,
             pc: 0,
             x: 1
         }
     }
 
-    fn tick(&mut self) {
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(Vec::new());
+        self
+    }
+
+    pub fn add_breakpoint(&mut self, pc: usize) {
+        self.breakpoints.insert(pc);
+    }
+
+    /// The cycle about to be executed.
+    pub fn cycle(&self) -> usize {
+        self.cycle
+    }
+
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// The value of the X register during the current cycle.
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
+    pub fn halted(&self) -> bool {
+        self.pc >= self.program.len()
+    }
+
+    pub fn trace(&self) -> &[Trace] {
+        self.trace.as_deref().unwrap_or_default()
+    }
+
+    /// Executes a single cycle. Instructions take effect at the end of their last cycle.
+    pub fn step(&mut self) -> Option<Trace> {
+        let instruction = self.program.get(self.pc)?;
+        let trace = Trace { cycle: self.cycle, pc: self.pc, x: self.x };
+
+        if let Some(log) = &mut self.trace {
+            log.push(trace);
+        }
+
         self.cycle += 1;
+        self.busy += 1;
 
-        if let Some(add) = self.adding.take() {
-            self.x += add;
+        let (cycles, x) = instruction.run(self.x);
+        if self.busy == cycles {
+            self.x = x;
+            self.busy = 0;
             self.pc += 1;
-            return;
         }
 
-        match self.program[self.pc] {
-            Instruction::Noop => self.pc += 1,
-            Instruction::Addx(add) => {
-                self.adding = Some(add);
-            },
-        }
+        Some(trace)
     }
 
-    fn print(&self) {
-        let pos = (self.cycle - 1) % 40;
+    /// Runs until `cycle` is about to be executed, the program ends, or an instruction with a
+    /// breakpoint is about to start. The breakpoint that last stopped execution is skipped, so
+    /// calling this again resumes execution.
+    pub fn run_until(&mut self, cycle: usize) -> Stop {
+        loop {
+            if self.cycle >= cycle {
+                return Stop::Cycle(self.cycle)
+            }
+
+            if self.halted() {
+                return Stop::Halted
+            }
+
+            let at_breakpoint = self.busy == 0 && self.breakpoints.contains(&self.pc);
+            if at_breakpoint && self.paused_at != Some(self.pc) {
+                self.paused_at = Some(self.pc);
+                return Stop::Breakpoint(self.pc)
+            }
 
-        if (self.x - pos as i32).abs() <= 1 {
-            print!("#");
-        } else {
-            print!(" ");
+            self.paused_at = None;
+            self.step();
         }
+    }
+
+    pub fn run(&mut self) -> Stop {
+        self.run_until(usize::MAX)
+    }
 
-        if pos == 39 {
-            println!()
+    /// The value of X during `cycle`, or `None` if the program halts first.
+    pub fn probe(&mut self, cycle: usize) -> Option<i32> {
+        loop {
+            match self.run_until(cycle) {
+                Stop::Cycle(_) => return Some(self.x),
+                Stop::Breakpoint(_) => continue,
+                Stop::Halted => return None,
+            }
         }
     }
 
-    fn signal(&self) -> i32 {
-        self.x * self.cycle as i32
+    /// Whether the CRT pixel drawn during the current cycle is lit.
+    pub fn lit(&self) -> bool {
+        let pos = (self.cycle - 1) % WIDTH;
+        (self.x - pos as i32).abs() <= 1
     }
 }
 
 #[aoc(day10, part1)]
-fn part1(program: &Vec<Instruction>) -> i32 {
-    let mut signal = 0;
+fn part1(program: &[Instruction]) -> Option<i32> {
     let mut cpu = Cpu::new(program);
 
-    for i in 1..=220 {
-        if (i - 20) % 40 == 0 {
-            signal += cpu.signal();
+    (20..=220).step_by(40)
+        .map(|cycle| Some(cpu.probe(cycle)? * cycle as i32))
+        .sum()
+}
+
+#[aoc(day10, part2)]
+fn part2(program: &[Instruction]) -> i32 {
+    let screen = Screen::draw(program);
+
+    for row in &screen.pixels {
+        for (x, &lit) in row.iter().enumerate() {
+            if x % 5 == 0 {
+                if x % 10 == 0 {
+                    print!("\u{001b}[31;1m"); // bright red
+                } else {
+                    print!("\u{001b}[32;1m"); // bright green
+                }
+            }
+            print!("{}", if lit { '#' } else { ' ' });
         }
+        println!()
+    }
+
+    println!("\u{001b}[0m"); // reset
+
+    0
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program(pub Vec<Instruction>);
+
+impl FromStr for Program {
+    type Err = ParseFailure;
 
-        cpu.tick();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_input(instructions(), &normalize(s), "program").map(Program)
     }
+}
 
-    signal
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instruction in &self.0 {
+            writeln!(f, "{}", instruction)?;
+        }
+        Ok(())
+    }
 }
 
-#[aoc(day10, part2)]
-fn part2(program: &Vec<Instruction>) -> i32 {
-    let mut cpu = Cpu::new(program);
+const WIDTH: usize = 40;
+const HEIGHT: usize = 6;
+
+/// Sprite positions worth considering: anything further out lights no pixels at all.
+const XS: RangeInclusive<i32> = -1..=WIDTH as i32;
+
+fn index(x: i32) -> usize {
+    (x - XS.start()) as usize
+}
+
+#[derive(Display, Debug, PartialEq, Eq)]
+pub enum DrawError {
+    #[display("screen must be 6 rows of 40 pixels")]
+    Size,
+    #[display("unexpected pixel {0:?}")]
+    Pixel(char),
+    #[display("no glyph for {0:?}")]
+    Glyph(char),
+    #[display("at most 8 letters fit on the screen")]
+    TooLong,
+    #[display("pixel ({0}, {1}) cannot be drawn")]
+    Unreachable(usize, usize),
+}
+
+impl std::error::Error for DrawError {}
+
+/// The subset of the AoC letter font seen in puzzle answers. Each glyph is 4 pixels wide and
+/// is followed by a blank column.
+const FONT: [(char, [&str; HEIGHT]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Screen {
+    pixels: [[bool; WIDTH]; HEIGHT],
+}
 
-    for i in 0..240 {
-        if i % 5 == 0 {
-            if i % 10 == 0 {
-                print!("\u{001b}[31;1m"); // bright red
-            } else {
-                print!("\u{001b}[32;1m"); // bright green
+impl Screen {
+    /// Runs `program` on the CPU and captures what the CRT draws.
+    pub fn draw(program: &[Instruction]) -> Self {
+        let mut pixels = [[false; WIDTH]; HEIGHT];
+        let mut cpu = Cpu::new(program);
+
+        for pixel in pixels.iter_mut().flatten() {
+            *pixel = cpu.lit();
+
+            if cpu.step().is_none() {
+                break
             }
         }
-        cpu.print();
-        cpu.tick();
+
+        Self { pixels }
     }
 
-    println!("\u{001b}[0m"); // reset
+    pub fn from_text(text: &str) -> Result<Self, DrawError> {
+        if text.chars().count() > WIDTH / 5 {
+            return Err(DrawError::TooLong)
+        }
 
-    0
-}
\ No newline at end of file
+        let mut pixels = [[false; WIDTH]; HEIGHT];
+
+        for (i, c) in text.chars().enumerate().filter(|&(_, c)| c != ' ') {
+            let (_, glyph) = FONT.iter().find(|(g, _)| *g == c).ok_or(DrawError::Glyph(c))?;
+
+            for (row, line) in pixels.iter_mut().zip(glyph) {
+                for (pixel, b) in row[i * 5..].iter_mut().zip(line.bytes()) {
+                    *pixel = b == b'#';
+                }
+            }
+        }
+
+        Ok(Self { pixels })
+    }
+
+    /// Reads the screen back as text, if every glyph is blank or in the font.
+    pub fn text(&self) -> Option<String> {
+        let text: Option<String> = (0..WIDTH / 5).map(|i| {
+            FONT.iter()
+                .chain(&[(' ', ["...."; HEIGHT])])
+                .find(|(_, glyph)| {
+                    glyph.iter().zip(&self.pixels).all(|(line, row)| {
+                        line.bytes().zip(&row[i * 5..]).all(|(b, &lit)| (b == b'#') == lit)
+                    })
+                })
+                .map(|&(c, _)| c)
+        }).collect();
+
+        text.map(|text| text.trim_end().to_string())
+    }
+
+    /// Generates a program that draws this screen. The CPU starts with X = 1 and cannot move
+    /// the sprite before the third cycle, so the first two pixels must be lit.
+    pub fn assemble(&self) -> Result<Program, DrawError> {
+        let cycles = WIDTH * HEIGHT;
+        let pixels: Vec<bool> = self.pixels.iter().flatten().copied().collect();
+        let lit = |cycle: usize, x: i32| (x - (cycle % WIDTH) as i32).abs() <= 1;
+        let valid = |cycle: usize, x: i32| lit(cycle, x) == pixels[cycle];
+
+        // The program is a series of segments with constant X. parent[cycle][x] records the
+        // (start, x) of the segment before one that holds X = x from `cycle`. Every segment but
+        // the last ends with an `addx`, so is at least two cycles long.
+        let mut parent: Vec<Vec<Option<(usize, i32)>>> = vec![vec![None; XS.count()]; cycles];
+        let mut reached = 0;
+        parent[0][index(1)] = Some((0, 1));
+
+        for start in 0..cycles {
+            for x in XS {
+                if parent[start][index(x)].is_none() {
+                    continue
+                }
+
+                for end in start..cycles {
+                    if !valid(end, x) {
+                        break
+                    }
+                    reached = reached.max(end + 1);
+
+                    if end + 1 == cycles {
+                        return Ok(Self::program(&parent, (start, x), cycles - start))
+                    }
+
+                    if end > start {
+                        for next in XS.filter(|&next| valid(end + 1, next)) {
+                            parent[end + 1][index(next)].get_or_insert((start, x));
+                        }
+                    }
+                }
+            }
+        }
+
+        Err(DrawError::Unreachable(reached % WIDTH, reached / WIDTH))
+    }
+
+    fn program(parent: &[Vec<Option<(usize, i32)>>], (mut start, mut x): (usize, i32), len: usize) -> Program {
+        let mut program = vec![Instruction::Noop; len];
+
+        while start > 0 {
+            let (prev_start, prev_x) = parent[start][index(x)].unwrap();
+            program.push(Instruction::Addx(x - prev_x));
+            program.extend(std::iter::repeat_n(Instruction::Noop, start - prev_start - 2));
+            (start, x) = (prev_start, prev_x);
+        }
+
+        program.reverse();
+        Program(program)
+    }
+}
+
+impl FromStr for Screen {
+    type Err = DrawError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pixels = [[false; WIDTH]; HEIGHT];
+        let lines: Vec<&str> = s.lines().collect();
+
+        if lines.len() != HEIGHT || lines.iter().any(|line| line.chars().count() != WIDTH) {
+            return Err(DrawError::Size)
+        }
+
+        for (row, line) in pixels.iter_mut().zip(lines) {
+            for (pixel, c) in row.iter_mut().zip(line.chars()) {
+                *pixel = match c {
+                    '#' => true,
+                    '.' => false,
+                    c => return Err(DrawError::Pixel(c)),
+                };
+            }
+        }
+
+        Ok(Self { pixels })
+    }
+}
+
+impl fmt::Display for Screen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in &self.pixels {
+            for &lit in row {
+                write!(f, "{}", if lit { '#' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    fn round_trip(text: &str) {
+        let screen = Screen::from_text(text).unwrap();
+        let program = screen.assemble().unwrap();
+
+        assert_eq!(Screen::draw(&program.0), screen);
+        assert_eq!(Screen::draw(&program.0).text().as_deref(), Some(text));
+        assert_eq!(program.to_string().parse::<Program>().unwrap(), program);
+    }
+
+    #[test]
+    fn assembled_programs_draw_their_screen() {
+        round_trip("RBPARAGF");
+        round_trip("Z");
+        round_trip("EHPZPJGL");
+    }
+
+    #[test]
+    fn unlit_first_pixel_cannot_be_drawn() {
+        assert_eq!(Screen::from_text("A").unwrap().assemble(), Err(DrawError::Unreachable(0, 0)));
+    }
+
+    #[test]
+    fn breakpoints_stop_before_their_instruction() {
+        let program = [Instruction::Noop, Instruction::Addx(3), Instruction::Noop];
+        let mut cpu = Cpu::new(&program);
+        cpu.add_breakpoint(0);
+        cpu.add_breakpoint(2);
+
+        assert_eq!(cpu.run(), Stop::Breakpoint(0));
+        assert_eq!(cpu.run_until(1), Stop::Cycle(1));
+        assert_eq!(cpu.run(), Stop::Breakpoint(2));
+        assert_eq!((cpu.cycle(), cpu.x()), (4, 4));
+        assert_eq!(cpu.run(), Stop::Halted);
+    }
+
+    #[test]
+    fn programs_round_trip() {
+        let program = Program(vec![Instruction::Noop, Instruction::Addx(3), Instruction::Addx(-5)]);
+
+        assert_eq!(program.to_string(), "noop\naddx 3\naddx -5\n");
+        assert_eq!(program.to_string().parse::<Program>().unwrap(), program);
+    }
+
+    #[test]
+    fn screens_round_trip() {
+        let screen = Screen::from_text("RBPARAGF").unwrap();
+
+        assert_eq!(screen.to_string().parse::<Screen>(), Ok(screen));
+    }
+
+    #[test]
+    fn program_parses_with_any_line_endings() {
+        let program = Screen::from_text("Z").unwrap().assemble().unwrap();
+
+        for data in variants(&program.to_string()) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!(parsed, program.0, "{:?}", data);
+            assert_eq!(part1(&parsed), Some(580));
+            assert_eq!(Screen::draw(&parsed).text().as_deref(), Some("Z"));
+        }
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("noop\naddx x"), &[("unknown instruction \"addx x\"", 5..11)]);
+        assert_fails(parse("noop\njmp 3\nnoop"), &[("unknown instruction \"jmp 3\"", 5..10)]);
+        assert_fails(parse("addx 99999999999"), &[("unknown instruction \"addx 99999999999\"", 0..16)]);
+    }
+
+    const SMALL: &str = "noop\naddx 3\naddx -5";
+
+    #[test]
+    fn small_example_is_traced() {
+        let program = parse(SMALL).unwrap();
+        let mut cpu = Cpu::new(&program).with_trace();
+
+        assert_eq!(cpu.run(), Stop::Halted);
+        assert_eq!((cpu.cycle(), cpu.x()), (6, -1));
+        assert_eq!(cpu.trace().iter().map(|t| (t.cycle, t.pc, t.x)).collect::<Vec<_>>(),
+            [(1, 0, 1), (2, 1, 1), (3, 1, 1), (4, 2, 4), (5, 2, 4)]);
+        assert!(Cpu::new(&program).trace().is_empty());
+    }
+
+    #[test]
+    fn probe_reads_x_during_a_cycle() {
+        let program = parse(SMALL).unwrap();
+        let mut cpu = Cpu::new(&program);
+        cpu.add_breakpoint(1);
+
+        assert_eq!([1, 2, 3, 4, 5, 6].map(|cycle| cpu.probe(cycle)), [Some(1), Some(1), Some(1), Some(4), Some(4), Some(-1)]);
+        assert_eq!(cpu.probe(7), None);
+    }
+}
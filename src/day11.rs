@@ -1,20 +1,124 @@
-use std::{collections::VecDeque, cmp::Reverse};
+use std::{collections::{HashMap, VecDeque}, cmp::Reverse, hash::Hash};
 
 use aoc_runner_derive::aoc;
+use parse_display::Display;
 
-struct Monkey {
-    items: VecDeque<u64>,
-    operation: fn(u64) -> u64,
+use crate::biguint::BigUint;
+
+/// A worry level. Arithmetic is checked, returning `None` rather than wrapping on overflow.
+pub trait Worry: Clone + Eq + Hash {
+    fn from_u64(n: u64) -> Self;
+    fn add(&self, n: u64) -> Option<Self>;
+    fn mul(&self, other: &Self) -> Option<Self>;
+    fn div(&self, n: u64) -> Self;
+    fn rem(&self, n: u64) -> u64;
+}
+
+impl Worry for u64 {
+    fn from_u64(n: u64) -> Self {
+        n
+    }
+
+    fn add(&self, n: u64) -> Option<Self> {
+        self.checked_add(n)
+    }
+
+    fn mul(&self, other: &Self) -> Option<Self> {
+        self.checked_mul(*other)
+    }
+
+    fn div(&self, n: u64) -> Self {
+        self / n
+    }
+
+    fn rem(&self, n: u64) -> u64 {
+        self % n
+    }
+}
+
+impl Worry for u128 {
+    fn from_u64(n: u64) -> Self {
+        n as u128
+    }
+
+    fn add(&self, n: u64) -> Option<Self> {
+        self.checked_add(n as u128)
+    }
+
+    fn mul(&self, other: &Self) -> Option<Self> {
+        self.checked_mul(*other)
+    }
+
+    fn div(&self, n: u64) -> Self {
+        self / n as u128
+    }
+
+    fn rem(&self, n: u64) -> u64 {
+        (self % n as u128) as u64
+    }
+}
+
+impl Worry for BigUint {
+    fn from_u64(n: u64) -> Self {
+        n.into()
+    }
+
+    fn add(&self, n: u64) -> Option<Self> {
+        Some(self.add_u64(n))
+    }
+
+    fn mul(&self, other: &Self) -> Option<Self> {
+        Some(BigUint::mul(self, other))
+    }
+
+    fn div(&self, n: u64) -> Self {
+        self.div_rem_u64(n).0
+    }
+
+    fn rem(&self, n: u64) -> u64 {
+        self.div_rem_u64(n).1
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Operation {
+    Add(u64),
+    Mul(u64),
+    Square,
+}
+
+impl Operation {
+    fn apply<W: Worry>(&self, old: &W) -> Option<W> {
+        match self {
+            Operation::Add(n) => old.add(*n),
+            Operation::Mul(n) => old.mul(&W::from_u64(*n)),
+            Operation::Square => old.mul(old),
+        }
+    }
+}
+
+#[derive(Display, Debug, PartialEq, Eq)]
+#[display("worry level overflowed when monkey {monkey} inspected an item in round {round}")]
+pub struct Overflow {
+    pub monkey: usize,
+    pub round: u64,
+}
+
+impl std::error::Error for Overflow {}
+
+pub struct Monkey<W> {
+    items: VecDeque<W>,
+    operation: Operation,
     test: u64,
     if_true: usize,
     if_false: usize,
-    inspection_count: usize,
+    inspection_count: u64,
 }
 
-impl Monkey {
-    fn new<const N: usize>(items: [u64; N], operation: fn(u64) -> u64, test: u64, if_true: usize, if_false: usize) -> Self {
+impl<W: Worry> Monkey<W> {
+    fn new<const N: usize>(items: [u64; N], operation: Operation, test: u64, if_true: usize, if_false: usize) -> Self {
         Monkey {
-            items: VecDeque::from(items),
+            items: items.into_iter().map(W::from_u64).collect(),
             operation,
             test,
             if_true,
@@ -23,14 +127,18 @@ impl Monkey {
         }
     }
 
-    fn inspect(&mut self, stress_damper: impl Fn(u64) -> u64) -> Option<(usize, u64)> {
-        let mut item = self.items.pop_front()?;
-        item = (self.operation)(item);
-        item = (stress_damper)(item);
-
+    fn inspect(&mut self, stress_damper: impl Fn(W) -> W) -> Option<Option<(usize, W)>> {
+        let item = self.items.pop_front()?;
         self.inspection_count += 1;
 
-        let index = if item % self.test == 0 {
+        Some(self.throw(&item, stress_damper))
+    }
+
+    /// Where this monkey throws `item` and with what worry level, or `None` on overflow.
+    fn throw(&self, item: &W, stress_damper: impl Fn(W) -> W) -> Option<(usize, W)> {
+        let item = (stress_damper)(self.operation.apply(item)?);
+
+        let index = if item.rem(self.test) == 0 {
             self.if_true
         } else {
             self.if_false
@@ -39,46 +147,179 @@ impl Monkey {
         Some((index, item))
     }
 
-    fn add(&mut self, item: u64) {
+    fn add(&mut self, item: W) {
         self.items.push_back(item)
     }
 }
 
-fn monkeys() -> [Monkey; 8] { 
+pub fn monkeys<W: Worry>() -> [Monkey<W>; 8] {
     [
-        Monkey::new([85, 79, 63, 72], |old| old * 17, 2, 2, 6),
-        Monkey::new([53, 94, 65, 81, 93, 73, 57, 92], |old| old * old, 7, 0, 2),
-        Monkey::new([62, 63], |old| old + 7, 13, 7, 6),
-        Monkey::new([57, 92, 56], |old| old + 4, 5, 4, 5),
-        Monkey::new([67], |old| old + 5, 3, 1, 5),
-        Monkey::new([85, 56, 66, 72, 57, 99], |old| old + 6, 19, 1, 0),
-        Monkey::new([86, 65, 98, 97, 69], |old| old * 13, 11, 3, 7),
-        Monkey::new([87, 68, 92, 66, 91, 50, 68], |old| old + 2, 17, 4, 3)
+        Monkey::new([85, 79, 63, 72], Operation::Mul(17), 2, 2, 6),
+        Monkey::new([53, 94, 65, 81, 93, 73, 57, 92], Operation::Square, 7, 0, 2),
+        Monkey::new([62, 63], Operation::Add(7), 13, 7, 6),
+        Monkey::new([57, 92, 56], Operation::Add(4), 5, 4, 5),
+        Monkey::new([67], Operation::Add(5), 3, 1, 5),
+        Monkey::new([85, 56, 66, 72, 57, 99], Operation::Add(6), 19, 1, 0),
+        Monkey::new([86, 65, 98, 97, 69], Operation::Mul(13), 11, 3, 7),
+        Monkey::new([87, 68, 92, 66, 91, 50, 68], Operation::Add(2), 17, 4, 3)
     ]
 }
 
-fn simulate(monkeys: &mut [Monkey; 8], rounds: usize, stress_damper: impl Fn(u64) -> u64) -> usize {
-    for _ in 0..rounds {
+/// The product of every monkey's test divisor, modulo which worry levels can be reduced
+/// without changing where any item is thrown.
+pub fn test_product<W>(monkeys: &[Monkey<W>]) -> u64 {
+    monkeys.iter().map(|m| m.test).product()
+}
+
+/// Items never interact, so each one is followed on its own. Once an item is back at the
+/// start of a round with a state seen before, the inspections of the remaining rounds are
+/// extrapolated from that cycle.
+pub fn simulate<W: Worry>(monkeys: &[Monkey<W>], rounds: u64, stress_damper: impl Fn(W) -> W) -> Result<u128, Overflow> {
+    let mut counts = vec![0; monkeys.len()];
+
+    for (index, monkey) in monkeys.iter().enumerate() {
+        for item in &monkey.items {
+            let item_counts = item_inspections(monkeys, (index, item.clone()), rounds, &stress_damper)?;
+
+            for (count, item_count) in counts.iter_mut().zip(item_counts) {
+                *count += item_count;
+            }
+        }
+    }
+
+    Ok(monkey_business(counts))
+}
+
+fn item_inspections<W: Worry>(monkeys: &[Monkey<W>], mut state: (usize, W), rounds: u64, stress_damper: impl Fn(W) -> W) -> Result<Vec<u64>, Overflow> {
+    let mut seen = HashMap::new();
+    let mut history = vec![vec![0; monkeys.len()]];
+
+    for round in 0..rounds {
+        if let Some(start) = seen.insert(state.clone(), round) {
+            let (start, round) = (start as usize, round as usize);
+            let len = (round - start) as u64;
+            let (cycles, rest) = ((rounds - round as u64) / len, ((rounds - round as u64) % len) as usize);
+
+            return Ok((0..monkeys.len()).map(|i| {
+                let cycle = history[round][i] - history[start][i];
+                history[round][i] + cycles * cycle + history[start + rest][i] - history[start][i]
+            }).collect())
+        }
+
+        let mut counts = history[round as usize].clone();
+        let (mut index, mut item) = state;
+
+        loop {
+            counts[index] += 1;
+            let (next, worry) = monkeys[index].throw(&item, &stress_damper)
+                .ok_or(Overflow { monkey: index, round: round + 1 })?;
+            let next_round = next < index;
+            (index, item) = (next, worry);
+
+            if next_round {
+                break
+            }
+        }
+
+        state = (index, item);
+        history.push(counts);
+    }
+
+    Ok(history.pop().unwrap())
+}
+
+fn monkey_business(mut counts: Vec<u64>) -> u128 {
+    counts.sort_by_key(|&count| Reverse(count));
+    counts[0] as u128 * counts[1] as u128
+}
+
+/// Plays every round in turn, holding each item's worry level as it goes.
+pub fn simulate_rounds<W: Worry>(monkeys: &mut [Monkey<W>], rounds: u64, stress_damper: impl Fn(W) -> W) -> Result<u128, Overflow> {
+    for round in 1..=rounds {
         for i in 0..monkeys.len() {
-            while let Some((index, item)) = monkeys[i].inspect(&stress_damper) {
+            while let Some(thrown) = monkeys[i].inspect(&stress_damper) {
+                let (index, item) = thrown.ok_or(Overflow { monkey: i, round })?;
                 monkeys[index].add(item);
             }
         }
     }
 
-    monkeys.sort_by_key(|monkey| Reverse(monkey.inspection_count));
-    monkeys[0].inspection_count * monkeys[1].inspection_count
+    Ok(monkey_business(monkeys.iter().map(|monkey| monkey.inspection_count).collect()))
 }
 
 #[aoc(day11, part1)]
-fn part1(_: &[u8]) -> usize {
-    let mut monkeys = monkeys();
-    simulate(&mut monkeys, 20, |item| item / 3)
+fn part1(_: &[u8]) -> Result<u128, Overflow> {
+    simulate(&monkeys::<u64>(), 20, |item| item / 3)
+}
+
+#[aoc(day11, part1, BigUint)]
+fn part1_biguint(_: &[u8]) -> Result<u128, Overflow> {
+    simulate_rounds(&mut monkeys::<BigUint>(), 20, |item| item.div(3))
 }
 
 #[aoc(day11, part2)]
-fn part2(_: &[u8]) -> usize {
-    let mut monkeys = monkeys();
-    let product: u64 = monkeys.iter().map(|m| m.test).product();
-    simulate(&mut monkeys, 10000, |item| item % product)
-}
\ No newline at end of file
+fn part2(_: &[u8]) -> Result<u128, Overflow> {
+    let monkeys = monkeys::<u64>();
+    let product = test_product(&monkeys);
+    simulate(&monkeys, 10000, |item| item % product)
+}
+
+#[aoc(day11, part2, Rounds)]
+fn part2_rounds(_: &[u8]) -> Result<u128, Overflow> {
+    let mut monkeys = monkeys::<u64>();
+    let product = test_product(&monkeys);
+    simulate_rounds(&mut monkeys, 10000, |item| item % product)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reduced_worry_matches_unreduced() {
+        let product = test_product(&monkeys::<u64>());
+
+        for rounds in [5, 10, 15] {
+            let reduced = simulate(&monkeys::<u64>(), rounds, |item| item % product);
+
+            assert_eq!(reduced, simulate_rounds(&mut monkeys::<BigUint>(), rounds, |item| item), "{} rounds", rounds);
+        }
+    }
+
+    #[test]
+    fn u128_overflows_unreduced() {
+        assert_eq!(simulate_rounds(&mut monkeys::<u128>(), 12, |item| item), Ok(42016));
+        assert_eq!(simulate_rounds(&mut monkeys::<u128>(), 13, |item| item), Err(Overflow { monkey: 6, round: 13 }));
+        assert_eq!(simulate_rounds(&mut monkeys::<u64>(), 20, |item| item).map_err(|e| e.round), Err(5));
+    }
+
+    #[test]
+    fn extrapolated_cycles_match_every_round() {
+        let product = test_product(&monkeys::<u64>());
+
+        // Every item settles into a cycle of 80 rounds by round 417. 487 rounds ends one item
+        // exactly on a cycle boundary, and the rest part way through one
+        for rounds in [20, 487, 1000, 10000] {
+            assert_eq!(
+                simulate(&monkeys::<u64>(), rounds, |item| item % product),
+                simulate_rounds(&mut monkeys::<u64>(), rounds, |item| item % product),
+                "{} rounds", rounds,
+            );
+        }
+
+        for rounds in [20, 1000] {
+            assert_eq!(
+                simulate(&monkeys::<u64>(), rounds, |item| item / 3),
+                simulate_rounds(&mut monkeys::<u64>(), rounds, |item| item / 3),
+                "{} rounds", rounds,
+            );
+        }
+    }
+
+    #[test]
+    fn extrapolates_far_ahead() {
+        let product = test_product(&monkeys::<u64>());
+
+        assert_eq!(simulate(&monkeys::<u64>(), 1_000_000_000_000, |item| item % product), Ok(323999999993340000000018600));
+    }
+}
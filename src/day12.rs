@@ -1,8 +1,11 @@
-use std::{collections::{HashSet, BinaryHeap}, ops::Index, cmp::Reverse};
+use std::{collections::BinaryHeap, ops::Index, cmp::Reverse};
 
 use aoc_runner_derive::{aoc, aoc_generator};
+use chumsky::prelude::*;
 
-struct Grid {
+use crate::parse::{lines, normalize, input_name, parse_input, ParseFailure};
+
+pub struct Grid {
     start: (usize, usize),
     dest: (usize, usize),
     width: usize,
@@ -10,56 +13,223 @@ struct Grid {
     grid: Vec<Vec<u8>>
 }
 
+/// Decides which steps are allowed and what they cost.
+pub trait Movement {
+    fn directions(&self) -> &[(isize, isize)];
+
+    /// The cost of stepping from `from` to the adjacent cell `to`, or `None` if not allowed.
+    fn cost(&self, grid: &Grid, from: (usize, usize), to: (usize, usize)) -> Option<usize>;
+}
+
+const ORTHOGONAL: [(isize, isize); 4] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+];
+
+const DIAGONAL: [(isize, isize); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// Movement limited by how far a single step may climb or descend, with a cost depending on
+/// the heights of both cells.
+#[derive(Clone, Copy)]
+pub struct Rules {
+    pub max_climb: u8,
+    pub max_descent: u8,
+    pub diagonal: bool,
+    pub cost: fn(u8, u8) -> usize,
+}
+
+impl Rules {
+    pub const PUZZLE: Rules = Rules {
+        max_climb: 1,
+        max_descent: u8::MAX,
+        diagonal: false,
+        cost: |_, _| 1,
+    };
+
+    /// Counts only the steps that climb.
+    pub const FEWEST_CLIMBS: Rules = Rules {
+        cost: |from, to| (to > from) as usize,
+        ..Self::PUZZLE
+    };
+
+    /// Never climbs, and may drop any distance.
+    pub const SKI: Rules = Rules {
+        max_climb: 0,
+        ..Self::PUZZLE
+    };
+}
+
+impl Movement for Rules {
+    fn directions(&self) -> &[(isize, isize)] {
+        if self.diagonal {
+            &DIAGONAL
+        } else {
+            &ORTHOGONAL
+        }
+    }
+
+    fn cost(&self, grid: &Grid, from: (usize, usize), to: (usize, usize)) -> Option<usize> {
+        let (from, to) = (grid[from], grid[to]);
+
+        if to > from.saturating_add(self.max_climb) || to < from.saturating_sub(self.max_descent) {
+            None
+        } else {
+            Some((self.cost)(from, to))
+        }
+    }
+}
+
+/// The cheapest cost between every cell and its nearest seed, along with the next step on
+/// the way there.
+pub struct DistanceField {
+    distance: Vec<Vec<Option<usize>>>,
+    next: Vec<Vec<Option<(usize, usize)>>>,
+    towards: bool,
+}
+
+impl DistanceField {
+    pub fn get(&self, (x, y): (usize, usize)) -> Option<usize> {
+        self.distance[y][x]
+    }
+
+    pub fn rows(&self) -> &[Vec<Option<usize>>] {
+        &self.distance
+    }
+
+    /// The cheapest route between `point` and its nearest seed, in the order it is walked.
+    pub fn path(&self, point: (usize, usize)) -> Option<Vec<(usize, usize)>> {
+        self.get(point)?;
+
+        let mut path = vec![point];
+        let (mut x, mut y) = point;
+        while let Some(next) = self.next[y][x] {
+            path.push(next);
+            (x, y) = next;
+        }
+
+        if !self.towards {
+            path.reverse();
+        }
+
+        Some(path)
+    }
+}
+
 impl Grid {
-    fn new(mut grid: Vec<Vec<u8>>) -> Self {
+    /// Replaces the start `S` with `a` and the destination `E` with `z`. There must be one of
+    /// each, and every row as wide as the first.
+    pub fn new(mut grid: Vec<Vec<u8>>) -> Option<Self> {
         let height = grid.len();
-        let width = grid[0].len();
+        let width = grid.first()?.len();
+        if grid.iter().any(|row| row.len() != width) {
+            return None
+        }
 
         let mut start = None;
         let mut dest = None;
 
-        for y in 0..height {
-            for x in 0..width {
-                if grid[y][x] == b'S' {
+        for (y, row) in grid.iter_mut().enumerate() {
+            for (x, cell) in row.iter_mut().enumerate() {
+                if *cell == b'S' {
                     start = Some((x, y));
-                    grid[y][x] = b'a';
+                    *cell = b'a';
                 }
-                if grid[y][x] == b'E' {
+                if *cell == b'E' {
                     dest = Some((x, y));
-                    grid[y][x] = b'z';
+                    *cell = b'z';
                 }
             }
         }
 
-        Self {
-            start: start.unwrap(),
-            dest: dest.unwrap(),
+        Some(Self {
+            start: start?,
+            dest: dest?,
             width,
             height,
             grid
-        }
+        })
+    }
+
+    /// Searches from the destination until a cell matching `success` is found, returning its
+    /// distance and the path from that cell to the destination, or `None` if no matching cell
+    /// can reach the destination.
+    pub fn distance_from_dest(&self, success: impl Fn((usize, usize)) -> bool) -> Option<(usize, Vec<(usize, usize)>)> {
+        let (field, found) = self.search(&Rules::PUZZLE, [self.dest], true, success);
+        let found = found?;
+
+        Some((field.get(found)?, field.path(found)?))
+    }
+
+    /// The cost from every cell to the nearest of `targets`.
+    pub fn distances_to(&self, rules: &impl Movement, targets: impl IntoIterator<Item=(usize, usize)>) -> DistanceField {
+        self.search(rules, targets, true, |_| false).0
     }
 
-    pub fn distance_from_dest(&self, success: impl Fn((usize, usize)) -> bool) -> usize {
-        let mut visited = HashSet::new();
+    /// The cost to every cell from the nearest of `sources`.
+    pub fn distances_from(&self, rules: &impl Movement, sources: impl IntoIterator<Item=(usize, usize)>) -> DistanceField {
+        self.search(rules, sources, false, |_| false).0
+    }
+
+    /// Dijkstra outwards from `seeds`, following steps backwards if `towards` is set, until a
+    /// cell matching `stop` is settled.
+    fn search(
+        &self,
+        rules: &impl Movement,
+        seeds: impl IntoIterator<Item=(usize, usize)>,
+        towards: bool,
+        stop: impl Fn((usize, usize)) -> bool,
+    ) -> (DistanceField, Option<(usize, usize)>) {
+        let mut field = DistanceField {
+            distance: vec![vec![None; self.width]; self.height],
+            next: vec![vec![None; self.width]; self.height],
+            towards,
+        };
         let mut heap = BinaryHeap::new();
 
-        visited.insert(self.dest);
-        heap.push((Reverse(0usize), self.dest));
+        for (x, y) in seeds {
+            field.distance[y][x] = Some(0);
+            heap.push((Reverse(0usize), (x, y)));
+        }
+
+        while let Some((Reverse(cost), next)) = heap.pop() {
+            if field.get(next) != Some(cost) {
+                continue
+            }
 
-        while let Some((steps, next)) = heap.pop() {
-            if success(next) {
-                return steps.0
+            if stop(next) {
+                return (field, Some(next))
             }
 
-            for neighbor in self.neighbors(next) {
-                if visited.insert(neighbor) {
-                    heap.push((Reverse(steps.0 + 1), neighbor));
+            for &dir in rules.directions() {
+                let Some((x, y)) = self.add(next, dir) else { continue };
+                let step = if towards {
+                    rules.cost(self, (x, y), next)
+                } else {
+                    rules.cost(self, next, (x, y))
+                };
+
+                if let Some(step) = step {
+                    if field.distance[y][x].is_none_or(|d| cost + step < d) {
+                        field.distance[y][x] = Some(cost + step);
+                        field.next[y][x] = Some(next);
+                        heap.push((Reverse(cost + step), (x, y)));
+                    }
                 }
             }
         }
 
-        panic!("no solution")
+        (field, None)
     }
 
     fn add(&self, (x, y): (usize, usize), (dx, dy): (isize, isize)) -> Option<(usize, usize)> {
@@ -77,22 +247,51 @@ impl Grid {
         }
     }
 
-    fn can_step(&self, (x1, y1): (usize, usize), (x2, y2): (usize, usize)) -> bool {
-        self.grid[y1][x1] >= self.grid[y2][x2] - 1
+    fn letter(&self, point: (usize, usize)) -> char {
+        if point == self.start {
+            'S'
+        } else if point == self.dest {
+            'E'
+        } else {
+            self[point] as char
+        }
+    }
+
+    /// Draws the heightmap with `path` marked by arrows pointing along it, as in the puzzle.
+    /// Diagonal steps are drawn as `\` or `/`.
+    pub fn render_path(&self, path: &[(usize, usize)]) -> String {
+        let mut cells: Vec<Vec<char>> = (0..self.height)
+            .map(|y| (0..self.width).map(|x| self.letter((x, y))).collect())
+            .collect();
+
+        for pair in path.windows(2) {
+            let ((x1, y1), (x2, y2)) = (pair[0], pair[1]);
+            cells[y1][x1] = match (x2 as isize - x1 as isize, y2 as isize - y1 as isize) {
+                (1, 0) => '>',
+                (-1, 0) => '<',
+                (0, 1) => 'v',
+                (0, -1) => '^',
+                (1, 1) | (-1, -1) => '\\',
+                _ => '/',
+            };
+        }
+
+        cells.into_iter().map(|row| row.into_iter().chain(['\n']).collect::<String>()).collect()
     }
 
-    const DIRECTIONS: [(isize, isize); 4] = [
-        (1, 0),
-        (-1, 0),
-        (0, 1),
-        (0, -1),
-    ];
+    /// Shades each cell by its distance in `field`, from ` ` (nearest) to `@` (furthest).
+    /// Unreachable cells are drawn as `#`.
+    pub fn render_heatmap(&self, field: &DistanceField) -> String {
+        const SHADES: &[u8] = b" .:-=+*%@";
 
-    fn neighbors<'a>(&'a self, source: (usize, usize)) -> impl Iterator<Item=(usize, usize)> + 'a {
-        Self::DIRECTIONS.iter()
-            .copied()
-            .filter_map(move |dir| self.add(source, dir))
-            .filter(move |&to| self.can_step(to, source))
+        let max = field.rows().iter().flatten().flatten().copied().max().unwrap_or(0).max(1);
+
+        field.rows().iter().map(|row| {
+            row.iter().map(|distance| match distance {
+                Some(d) => SHADES[d * (SHADES.len() - 1) / max] as char,
+                None => '#',
+            }).chain(['\n']).collect::<String>()
+        }).collect()
     }
 }
 
@@ -104,18 +303,201 @@ impl Index<(usize, usize)> for Grid {
     }
 }
 
+/// Rows of heights from `a` to `z`, each as wide as the first, with one start `S` and one
+/// destination `E`.
+fn heightmap() -> impl Parser<char, Grid, Error = Simple<char>> {
+    let cell = filter(|c: &char| c.is_ascii_lowercase() || *c == 'S' || *c == 'E')
+        .labelled("height")
+        .map_with_span(|c, span| (c as u8, span));
+    let row = cell.repeated().at_least(1).map_with_span(|row, span| (row, span));
+
+    lines(row).try_map(|rows, span| {
+        let width = rows.first().map_or(0, |(row, _)| row.len());
+        let mut seen = Vec::new();
+
+        for (row, row_span) in &rows {
+            if row.len() != width {
+                return Err(Simple::custom(row_span.clone(), format!("row has {} cells, expected {}", row.len(), width)))
+            }
+
+            for (c, cell_span) in row {
+                if matches!(c, b'S' | b'E') {
+                    if seen.contains(c) {
+                        return Err(Simple::custom(cell_span.clone(), format!("there is already an {}", *c as char)))
+                    }
+                    seen.push(*c);
+                }
+            }
+        }
+
+        let grid = rows.into_iter().map(|(row, _)| row.into_iter().map(|(c, _)| c).collect()).collect();
+        Grid::new(grid).ok_or_else(|| Simple::custom(span, "the map needs a start S and a destination E"))
+    })
+}
+
 #[aoc_generator(day12)]
-fn parse(data: &str) -> Grid {
-    let grid = data.lines().map(|l| l.as_bytes().to_vec()).collect();
-    Grid::new(grid)
+fn parse(data: &str) -> Result<Grid, ParseFailure> {
+    let data = normalize(data);
+
+    parse_input(heightmap(), &data, &input_name(12))
 }
 
 #[aoc(day12, part1)]
-fn part1(grid: &Grid) -> usize{
-    grid.distance_from_dest(|p| p == grid.start)
+fn part1(grid: &Grid) -> Option<usize> {
+    Some(grid.distance_from_dest(|p| p == grid.start)?.0)
 }
 
 #[aoc(day12, part2)]
-fn part2(grid: &Grid) -> usize{
-    grid.distance_from_dest(|p| grid[p] == b'a')
-}
\ No newline at end of file
+fn part2(grid: &Grid) -> Option<usize> {
+    Some(grid.distance_from_dest(|p| grid[p] == b'a')?.0)
+}
+
+#[aoc(day12, part2, MultiSource)]
+fn part2_multi_source(grid: &Grid) -> Option<usize> {
+    let lowest = (0..grid.height)
+        .flat_map(|y| (0..grid.width).map(move |x| (x, y)))
+        .filter(|&p| grid[p] == b'a');
+
+    grid.distances_from(&Rules::PUZZLE, lowest).get(grid.dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "\
+Sabqponm
+abcryxxl
+accszExk
+acctuvwj
+abdefghi";
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((part1(&parsed), part2(&parsed)), (Some(31), Some(29)), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("Sabq\nabcE\nabc"), &[("row has 3 cells, expected 4", 10..13)]);
+        assert_fails(parse("Sabq\nabcd"), &[("the map needs a start S and a destination E", 0..9)]);
+        assert_fails(parse("SabS\nabcE"), &[("there is already an S", 3..4)]);
+    }
+
+    #[test]
+    fn example_path_is_drawn_with_arrows() {
+        let grid = parse(EXAMPLE).unwrap();
+        let (distance, path) = grid.distance_from_dest(|p| p == grid.start).unwrap();
+
+        assert_eq!((distance, path.len()), (31, 32));
+        assert_eq!((path[0], path[31]), (grid.start, grid.dest));
+
+        // The puzzle draws another of the shortest routes, which leaves S downwards first and
+        // joins this one at (2, 2)
+        assert_eq!(grid.render_path(&path), "\
+>>vv<<<<
+abvvv<<^
+acvv>E^^
+acv>>>^^
+ab>>>>>^
+");
+    }
+
+    #[test]
+    fn heatmaps_shade_by_distance() {
+        let grid = parse(EXAMPLE).unwrap();
+
+        assert_eq!(grid.render_heatmap(&grid.distances_to(&Rules::PUZZLE, [grid.dest])), "\
+@%%----=
+%%%:  .=
+@%*:  .=
+%**::..=
+%%**++++
+");
+        assert_eq!(grid.render_heatmap(&grid.distances_to(&Rules::SKI, [grid.dest])), "\
+########
+########
+####@ ##
+########
+########
+");
+    }
+
+    #[test]
+    fn unreachable_destinations_have_no_distance() {
+        let grid = parse("SzE").unwrap();
+
+        assert!(grid.distance_from_dest(|p| p == grid.start).is_none());
+        assert_eq!(part1(&grid), None);
+    }
+
+    /// Whether every step of `path` is to a neighbouring cell under `rules`.
+    fn walkable(grid: &Grid, rules: &Rules, path: &[(usize, usize)]) -> bool {
+        path.windows(2).all(|pair| {
+            let (dx, dy) = (pair[1].0 as isize - pair[0].0 as isize, pair[1].1 as isize - pair[0].1 as isize);
+            rules.directions().contains(&(dx, dy)) && rules.cost(grid, pair[0], pair[1]).is_some()
+        })
+    }
+
+    #[test]
+    fn distances_to_and_from_agree() {
+        let grid = parse(EXAMPLE).unwrap();
+        let to = grid.distances_to(&Rules::PUZZLE, [grid.dest]);
+        let from = grid.distances_from(&Rules::PUZZLE, [grid.start]);
+
+        assert_eq!((to.get(grid.start), from.get(grid.dest)), (Some(31), Some(31)));
+        assert_eq!((to.get(grid.dest), from.get(grid.start)), (Some(0), Some(0)));
+
+        let path = from.path(grid.dest).unwrap();
+        assert_eq!((path.len(), path[0], path[31]), (32, grid.start, grid.dest));
+        assert!(walkable(&grid, &Rules::PUZZLE, &path));
+    }
+
+    #[test]
+    fn fewest_climbs_only_counts_climbing() {
+        let grid = parse(EXAMPLE).unwrap();
+        let field = grid.distances_from(&Rules::FEWEST_CLIMBS, [grid.start]);
+
+        // Every climb is by one, so reaching `z` from `a` takes 25 of them
+        assert_eq!(field.get(grid.dest), Some(25));
+        assert_eq!(field.get((1, 0)), Some(0));
+        assert!(walkable(&grid, &Rules::FEWEST_CLIMBS, &field.path(grid.dest).unwrap()));
+    }
+
+    #[test]
+    fn skiing_never_climbs() {
+        let grid = parse(EXAMPLE).unwrap();
+
+        assert_eq!(grid.distances_from(&Rules::SKI, [grid.start]).get(grid.dest), None);
+
+        let downhill = grid.distances_from(&Rules::SKI, [grid.dest]);
+        // There is a route from E to S that never climbs and is no longer than the straight line
+        assert_eq!(downhill.get(grid.start), Some(7));
+        assert!(downhill.rows().iter().flatten().all(Option::is_some));
+    }
+
+    #[test]
+    fn diagonal_moves_cut_corners() {
+        let grid = parse(EXAMPLE).unwrap();
+        let rules = Rules { diagonal: true, ..Rules::PUZZLE };
+        let path = grid.distances_from(&rules, [grid.start]).path(grid.dest).unwrap();
+
+        assert_eq!(path.len(), 28);
+        assert_eq!(&path[..3], [(0, 0), (1, 1), (2, 2)]);
+        assert!(walkable(&grid, &rules, &path));
+        assert!(!walkable(&grid, &Rules::PUZZLE, &path));
+    }
+
+    #[test]
+    fn multi_source_matches_searching_from_the_destination() {
+        let grid = parse(EXAMPLE).unwrap();
+
+        assert_eq!(part2_multi_source(&grid), part2(&grid));
+        assert_eq!(part2_multi_source(&parse("SzE").unwrap()), None);
+    }
+}
@@ -1,20 +1,32 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
 use aoc_runner_derive::{aoc, aoc_generator};
 use chumsky::prelude::*;
 
-use crate::parse::parse_input;
+use crate::parse::{normalize, input_name, parse_input, ParseFailure};
 
 #[derive(Eq, Clone, Debug)]
-enum IntList {
+pub enum IntList {
     Int(i32),
     List(Vec<IntList>)
 }
 
+impl IntList {
+    /// Compares a scalar against a list as if it were a list of just that scalar.
+    fn cmp_singleton(a: &IntList, b: &[IntList]) -> Ordering {
+        match b.first() {
+            None => Ordering::Greater,
+            Some(first) => a.cmp(first).then(1.cmp(&b.len())),
+        }
+    }
+}
+
 impl Ord for IntList {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+    fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
             (IntList::Int(a), IntList::Int(b)) => a.cmp(b),
-            (a @ IntList::Int(_), b @ IntList::List(_)) => IntList::List(vec![a.clone()]).cmp(b),
-            (a @ IntList::List(_), b @ IntList::Int(_)) => a.cmp(&IntList::List(vec![b.clone()])),
+            (a @ IntList::Int(_), IntList::List(b)) => IntList::cmp_singleton(a, b),
+            (IntList::List(a), b @ IntList::Int(_)) => IntList::cmp_singleton(b, a).reverse(),
             (IntList::List(a), IntList::List(b)) => {
                 for (a, b) in a.iter().zip(b) {
                     let res = a.cmp(b);
@@ -22,14 +34,14 @@ impl Ord for IntList {
                         return res;
                     }
                 }
-                return a.len().cmp(&b.len())
+                a.len().cmp(&b.len())
             },
         }
     }
 }
 
 impl PartialOrd for IntList {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
     }
 }
@@ -40,28 +52,250 @@ impl PartialEq for IntList {
     }
 }
 
+/// Folds a base 10 integer a digit at a time, reporting it at its span if it doesn't fit.
+fn int_parser() -> impl Parser<char, i32, Error=Simple<char>> + Clone {
+    filter(char::is_ascii_digit)
+        .repeated()
+        .at_least(1)
+        .collect::<String>()
+        .labelled("number")
+        .try_map(|digits, span| {
+            digits.chars()
+                .try_fold(0i32, |int, digit| int.checked_mul(10)?.checked_add(digit as i32 - '0' as i32))
+                .ok_or_else(|| Simple::custom(span, format!("{} is out of range", digits)))
+        })
+}
+
 fn intlist_parser() -> impl Parser<char, IntList, Error=Simple<char>> {
     recursive(|bf| {
         choice((
-            text::int(10).map(|s: String| IntList::Int(s.parse().unwrap())),
+            int_parser().map(IntList::Int),
             just('[').ignore_then(bf.separated_by(just(','))).then_ignore(just(']')).map(IntList::List)
         ))
     })
 }
 
-#[aoc_generator(day13)]
-fn parse(data: &str) -> Vec<(IntList, IntList)> {
-    let parser = intlist_parser()
-        .then_ignore(text::newline())
+impl IntList {
+    /// `count` pseudo-random packets, each a list nested at most 5 lists deep, for testing
+    /// and benchmarking.
+    pub fn generate(count: usize, seed: u64) -> Vec<IntList> {
+        fn packet(state: &mut u64, depth: usize) -> IntList {
+            *state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let roll = (*state >> 33) as usize;
+
+            if depth == 0 || roll.is_multiple_of(3) {
+                IntList::Int((roll / 3 % 12) as i32)
+            } else {
+                IntList::List((0..roll / 3 % 4).map(|_| packet(state, depth - 1)).collect())
+            }
+        }
+
+        let mut state = seed;
+        (0..count).map(|_| IntList::List(vec![packet(&mut state, 4)])).collect()
+    }
+}
+
+impl FromStr for IntList {
+    type Err = ParseFailure;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_input(intlist_parser().then_ignore(end()), s, "packet")
+    }
+}
+
+impl fmt::Display for IntList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntList::Int(n) => write!(f, "{}", n),
+            IntList::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// The steps taken to compare two packets, as nested lines in the style of the puzzle.
+pub struct Explanation {
+    pub ordering: Ordering,
+    pub steps: Vec<(usize, String)>,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (depth, step) in &self.steps {
+            writeln!(f, "{:indent$}- {}", "", step, indent = depth * 2)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn explain(a: &IntList, b: &IntList) -> Explanation {
+    let mut steps = Vec::new();
+    let ordering = explain_into(a, b, 0, &mut steps);
+
+    Explanation { ordering, steps }
+}
+
+fn explain_into(a: &IntList, b: &IntList, depth: usize, steps: &mut Vec<(usize, String)>) -> Ordering {
+    steps.push((depth, format!("Compare {} vs {}", a, b)));
+
+    let ordering = match (a, b) {
+        (IntList::Int(x), IntList::Int(y)) => x.cmp(y),
+        (IntList::Int(_), IntList::List(_)) => {
+            let a = IntList::List(vec![a.clone()]);
+            steps.push((depth + 1, format!("Mixed types; convert left to {} and retry comparison", a)));
+            return explain_into(&a, b, depth + 1, steps)
+        },
+        (IntList::List(_), IntList::Int(_)) => {
+            let b = IntList::List(vec![b.clone()]);
+            steps.push((depth + 1, format!("Mixed types; convert right to {} and retry comparison", b)));
+            return explain_into(a, &b, depth + 1, steps)
+        },
+        (IntList::List(xs), IntList::List(ys)) => {
+            for (x, y) in xs.iter().zip(ys) {
+                let ordering = explain_into(x, y, depth + 1, steps);
+                if ordering.is_ne() {
+                    return ordering
+                }
+            }
+
+            let ordering = xs.len().cmp(&ys.len());
+            match ordering {
+                Ordering::Less => steps.push((depth + 1, "Left side ran out of items, so inputs are in the right order".to_string())),
+                Ordering::Greater => steps.push((depth + 1, "Right side ran out of items, so inputs are not in the right order".to_string())),
+                Ordering::Equal => {},
+            }
+            return ordering
+        },
+    };
+
+    match ordering {
+        Ordering::Less => steps.push((depth + 1, "Left side is smaller, so inputs are in the right order".to_string())),
+        Ordering::Greater => steps.push((depth + 1, "Right side is smaller, so inputs are not in the right order".to_string())),
+        Ordering::Equal => {},
+    }
+
+    ordering
+}
+
+fn pairs() -> impl Parser<char, Vec<(IntList, IntList)>, Error=Simple<char>> {
+    intlist_parser()
+        .then_ignore(just('\n'))
         .then(intlist_parser())
-        .separated_by(text::newline().then(text::newline()))
-        .then_ignore(end());
-    
-    parse_input(parser, data, 13)
+        .separated_by(just("\n\n"))
+        .then_ignore(end())
+}
+
+#[aoc_generator(day13)]
+fn parse(data: &str) -> Result<Vec<(IntList, IntList)>, ParseFailure> {
+    let data = normalize(data);
+
+    parse_input(pairs(), &data, &input_name(13))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Open,
+    Close,
+    /// The digits of an integer, so that comparing them never overflows.
+    Int(&'a [u8]),
+}
+
+/// Compares two runs of digits by the value they spell.
+fn cmp_digits(a: &[u8], b: &[u8]) -> Ordering {
+    let significant = |digits: &[u8]| digits.iter().position(|&d| d != b'0').map_or(0, |start| digits.len() - start);
+    let (a, b) = (&a[a.len() - significant(a)..], &b[b.len() - significant(b)..]);
+
+    a.len().cmp(&b.len()).then(a.cmp(b))
+}
+
+/// Reads tokens straight from a packet's bytes. An integer compared against a list is
+/// wrapped on the fly by replaying it, followed by a closing bracket for each list opened
+/// against it.
+struct Tokens<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    wrapped: Option<&'a [u8]>,
+    closes: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0, wrapped: None, closes: 0 }
+    }
+
+    fn wrap(&mut self, int: &'a [u8]) {
+        self.wrapped = Some(int);
+        self.closes += 1;
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if let Some(int) = self.wrapped.take() {
+            return Some(Token::Int(int))
+        }
+
+        if self.closes > 0 {
+            self.closes -= 1;
+            return Some(Token::Close)
+        }
+
+        // Anything besides brackets and digits only separates tokens
+        while matches!(self.bytes.get(self.pos), Some(byte) if !matches!(byte, b'[' | b']' | b'0'..=b'9')) {
+            self.pos += 1;
+        }
+
+        let token = match *self.bytes.get(self.pos)? {
+            b'[' => Token::Open,
+            b']' => Token::Close,
+            _ => {
+                let start = self.pos;
+                while self.bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+                    self.pos += 1;
+                }
+                return Some(Token::Int(&self.bytes[start..self.pos]))
+            }
+        };
+
+        self.pos += 1;
+        Some(token)
+    }
+}
+
+/// Compares two packets directly from their text, without building either tree.
+pub fn cmp_raw(a: &[u8], b: &[u8]) -> Ordering {
+    let (mut a, mut b) = (Tokens::new(a), Tokens::new(b));
+
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (Some(Token::Int(x)), Some(Token::Int(y))) => {
+                let ordering = cmp_digits(x, y);
+                if ordering.is_ne() {
+                    return ordering
+                }
+            },
+            (Some(Token::Open), Some(Token::Open)) | (Some(Token::Close), Some(Token::Close)) => {},
+            (Some(Token::Int(x)), Some(Token::Open)) => a.wrap(x),
+            (Some(Token::Open), Some(Token::Int(y))) => b.wrap(y),
+            (Some(Token::Close) | None, _) => return Ordering::Less,
+            (_, Some(Token::Close) | None) => return Ordering::Greater,
+        }
+    }
 }
 
 #[aoc(day13, part1)]
-fn part1(data: &Vec<(IntList, IntList)>) -> usize {
+fn part1(data: &[(IntList, IntList)]) -> usize {
     data.iter()
         .enumerate()
         .filter(|(_, (a, b))| a < b)
@@ -69,8 +303,70 @@ fn part1(data: &Vec<(IntList, IntList)>) -> usize {
         .sum()
 }
 
+/// Checks the packets against the grammar, but leaves them as text.
+#[aoc_generator(day13, part1, Raw)]
+fn parse_raw(data: &str) -> Result<String, ParseFailure> {
+    let data = normalize(data);
+
+    parse_input(pairs().ignored(), &data, &input_name(13))?;
+    Ok(data.into_owned())
+}
+
+#[aoc(day13, part1, Raw)]
+fn part1_raw(data: &str) -> usize {
+    let mut lines = data.lines().filter(|line| !line.is_empty());
+    let mut sum = 0;
+
+    for i in 1.. {
+        let (Some(a), Some(b)) = (lines.next(), lines.next()) else { break };
+
+        if cmp_raw(a.as_bytes(), b.as_bytes()).is_lt() {
+            sum += i;
+        }
+    }
+
+    sum
+}
+
+/// Every packet in order, including the dividers, along with the 1-based position of each
+/// divider. A divider is placed before any input packet that compares equal to it.
+pub struct SortedPackets {
+    pub packets: Vec<IntList>,
+    pub dividers: Vec<usize>,
+}
+
+pub fn sort_packets(pairs: &[(IntList, IntList)], dividers: &[IntList]) -> SortedPackets {
+    let mut tagged: Vec<(&IntList, Option<usize>)> = dividers.iter()
+        .enumerate()
+        .map(|(i, divider)| (divider, Some(i)))
+        .chain(pairs.iter().flat_map(|(a, b)| [(a, None), (b, None)]))
+        .collect();
+
+    // Stable, so dividers stay ahead of any equal packets
+    tagged.sort_by_key(|(packet, _)| *packet);
+
+    let mut positions = vec![0; dividers.len()];
+    for (position, (_, divider)) in tagged.iter().enumerate() {
+        if let Some(i) = divider {
+            positions[*i] = position + 1;
+        }
+    }
+
+    SortedPackets {
+        packets: tagged.into_iter().map(|(packet, _)| packet.clone()).collect(),
+        dividers: positions,
+    }
+}
+
 #[aoc(day13, part2)]
-fn part2(data: &Vec<(IntList, IntList)>) -> usize {
+fn part2(data: &[(IntList, IntList)]) -> usize {
+    let dividers = ["[[2]]", "[[6]]"].map(|divider| divider.parse().expect("divider packets are well formed"));
+
+    sort_packets(data, &dividers).dividers.iter().product()
+}
+
+#[aoc(day13, part2, Count)]
+fn part2_count(data: &[(IntList, IntList)]) -> usize {
     let mut first_count = 1;
     let mut second_count = 2;
 
@@ -83,5 +379,183 @@ fn part2(data: &Vec<(IntList, IntList)>) -> usize {
         }
     }
 
-    return first_count * second_count
+    first_count * second_count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "\
+[1,1,3,1,1]
+[1,1,5,1,1]
+
+[[1],[2,3,4]]
+[[1],4]
+
+[9]
+[[8,7,6]]
+
+[[4,4],4,4]
+[[4,4],4,4,4]
+
+[7,7,7,7]
+[7,7,7]
+
+[]
+[3]
+
+[[[]]]
+[[]]
+
+[1,[2,[3,[4,[5,6,7]]]],8,9]
+[1,[2,[3,[4,[5,6,0]]]],8,9]";
+
+    #[test]
+    fn raw_comparison_matches_trees() {
+        for pair in IntList::generate(20_000, 13).chunks(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            let (raw_a, raw_b) = (a.to_string(), b.to_string());
+
+            assert_eq!(cmp_raw(raw_a.as_bytes(), raw_b.as_bytes()), a.cmp(b), "{} vs {}", raw_a, raw_b);
+            assert_eq!(cmp_raw(raw_a.as_bytes(), raw_a.as_bytes()), Ordering::Equal, "{}", raw_a);
+        }
+    }
+
+    #[test]
+    fn raw_comparison_skips_spaces() {
+        assert_eq!(cmp_raw(b"[1, 2]", b"[1, 2]"), Ordering::Equal);
+        assert_eq!(cmp_raw(b"[1, [2]]", b"[1,3]"), Ordering::Less);
+    }
+
+    #[test]
+    fn raw_comparison_reads_any_length_of_digits() {
+        assert_eq!(cmp_raw(b"[99999999999]", b"[99999999998]"), Ordering::Greater);
+        assert_eq!(cmp_raw(b"[007]", b"[7]"), Ordering::Equal);
+        assert_eq!(cmp_raw(b"[0]", b"[00]"), Ordering::Equal);
+        assert_eq!(cmp_raw(b"[10]", b"[9]"), Ordering::Greater);
+    }
+
+    #[test]
+    fn out_of_range_numbers_are_reported() {
+        let failure = "[1,99999999999]".parse::<IntList>().unwrap_err();
+
+        assert_eq!(failure.errors().len(), 1);
+        assert_eq!(failure.errors()[0].span(), 3..14);
+        assert!(failure.to_string().contains("99999999999 is out of range"));
+        assert_eq!("[2147483647]".parse::<IntList>().unwrap(), IntList::List(vec![IntList::Int(i32::MAX)]));
+    }
+
+    #[test]
+    fn raw_packets_are_checked() {
+        assert!(parse_raw("[1,2]\n[1,2]").is_ok());
+        assert!(parse_raw("[1, 2]\n[1,2]").is_err());
+        assert!(parse_raw("[1,2]\n[1,x]").is_err());
+    }
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((part1(&parsed), part1_raw(&parse_raw(&data).unwrap()), part2(&parsed)), (13, 13, 140), "{:?}", data);
+        }
+    }
+
+    fn explained(a: &str, b: &str) -> (Ordering, String) {
+        let explanation = explain(&a.parse().unwrap(), &b.parse().unwrap());
+        (explanation.ordering, explanation.to_string())
+    }
+
+    #[test]
+    fn explanations_follow_the_puzzle() {
+        assert_eq!(explained("[[1],[2,3,4]]", "[[1],4]"), (Ordering::Less, "\
+- Compare [[1],[2,3,4]] vs [[1],4]
+  - Compare [1] vs [1]
+    - Compare 1 vs 1
+  - Compare [2,3,4] vs 4
+    - Mixed types; convert right to [4] and retry comparison
+    - Compare [2,3,4] vs [4]
+      - Compare 2 vs 4
+        - Left side is smaller, so inputs are in the right order
+".to_string()));
+    }
+
+    #[test]
+    fn explanations_say_which_side_ran_out() {
+        assert_eq!(explained("[7,7,7,7]", "[7,7,7]"), (Ordering::Greater, "\
+- Compare [7,7,7,7] vs [7,7,7]
+  - Compare 7 vs 7
+  - Compare 7 vs 7
+  - Compare 7 vs 7
+  - Right side ran out of items, so inputs are not in the right order
+".to_string()));
+
+        assert_eq!(explained("[]", "[3]"), (Ordering::Less, "\
+- Compare [] vs [3]
+  - Left side ran out of items, so inputs are in the right order
+".to_string()));
+
+        assert_eq!(explained("[[]]", "[[]]"), (Ordering::Equal, "- Compare [[]] vs [[]]\n  - Compare [] vs []\n".to_string()));
+    }
+
+    #[test]
+    fn packets_display_as_they_were_written() {
+        for packet in ["[]", "[[[]]]", "[1,[2,[3,[4,[5,6,7]]]],8,9]", "[10,[],[0]]"] {
+            assert_eq!(packet.parse::<IntList>().unwrap().to_string(), packet);
+        }
+
+        for packet in IntList::generate(1000, 35) {
+            let text = packet.to_string();
+            assert_eq!(text.parse::<IntList>().unwrap().to_string(), text);
+        }
+    }
+
+    fn packets(texts: &[&str]) -> Vec<IntList> {
+        texts.iter().map(|text| text.parse().unwrap()).collect()
+    }
+
+    fn pairs(texts: &[&str]) -> Vec<(IntList, IntList)> {
+        packets(texts).chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect()
+    }
+
+    #[test]
+    fn dividers_go_before_equal_packets() {
+        let sorted = sort_packets(&pairs(&["[[2]]", "[2]", "2", "[1]"]), &packets(&["[[2]]", "[[6]]"]));
+
+        assert_eq!(sorted.dividers, [2, 6]);
+        assert_eq!(
+            sorted.packets.iter().map(IntList::to_string).collect::<Vec<_>>(),
+            ["[1]", "[[2]]", "[[2]]", "[2]", "2", "[[6]]"],
+        );
+    }
+
+    #[test]
+    fn equal_dividers_keep_their_order() {
+        let sorted = sort_packets(&pairs(&["[2]", "[3]"]), &packets(&["[[2]]", "2", "[[2]]"]));
+
+        assert_eq!(sorted.dividers, [1, 2, 3]);
+        assert_eq!(sorted.packets.iter().map(IntList::to_string).collect::<Vec<_>>(), ["[[2]]", "2", "[[2]]", "[2]", "[3]"]);
+    }
+
+    #[test]
+    fn packets_come_back_sorted() {
+        let generated = IntList::generate(400, 36);
+        let pairs: Vec<_> = generated.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+        let sorted = sort_packets(&pairs, &packets(&["[[2]]", "[[6]]"]));
+
+        assert_eq!(sorted.packets.len(), 402);
+        assert!(sorted.packets.windows(2).all(|pair| pair[0] <= pair[1]));
+        for (divider, &position) in ["[[2]]", "[[6]]"].iter().zip(&sorted.dividers) {
+            assert_eq!(sorted.packets[position - 1].to_string(), *divider);
+        }
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("[1,2\n[3]"), &[("Unexpected token, expected one of: ,, ]", 4..5)]);
+        assert_fails(parse("[1,2]\n[3]\n[4]"), &[("Unexpected token, expected one of: \\n", 10..11)]);
+        assert_fails(parse("[1,a]\n[3]"), &[("Unexpected token, expected one of: [", 3..4)]);
+    }
 }
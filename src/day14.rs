@@ -3,16 +3,15 @@ use std::{collections::HashSet, iter};
 use aoc_runner_derive::{aoc, aoc_generator};
 use chumsky::prelude::*;
 
-use crate::parse::parse_input;
+use crate::parse::{lines, normalize, num, input_name, parse_input, ParseFailure};
 
 
-fn paths(data: &str) -> Vec<Vec<(i32, i32)>> {
-    let int = text::int(10).map(|s: String| s.parse().unwrap());
-    let pair = int.then_ignore(just(',')).then(int);
-    let path = pair.separated_by(just(" -> "));
-    let paths = path.separated_by(text::newline());
+fn paths(data: &str) -> Result<Vec<Vec<(i32, i32)>>, ParseFailure> {
+    let pair = num().then_ignore(just(',')).then(num());
+    let path = pair.separated_by(just(" -> ")).at_least(1);
+    let paths = lines(path);
 
-    parse_input(paths, data, 14)
+    parse_input(paths, data, &input_name(14))
 }
 
 fn range((x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> impl Iterator<Item=(i32, i32)> {
@@ -35,31 +34,149 @@ fn range((x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> impl Iterator<Item=(i32,
 }
 
 
+const SOURCE: (i32, i32) = (500, 0);
+
+#[derive(Clone)]
+struct Cave {
+    min_x: i32,
+    width: usize,
+    max_y: i32,
+    floor: i32,
+    blocked: Vec<bool>,
+}
+
+impl Cave {
+    fn new(rocks: &HashSet<(i32, i32)>) -> Self {
+        let max_y = rocks.iter().map(|&(_, y)| y).max().unwrap_or(0);
+        let floor = max_y + 2;
+
+        // Sand can never settle further than `floor` cells either side of the source
+        let min_x = rocks.iter().map(|&(x, _)| x).min().unwrap_or(SOURCE.0).min(SOURCE.0 - floor);
+        let max_x = rocks.iter().map(|&(x, _)| x).max().unwrap_or(SOURCE.0).max(SOURCE.0 + floor);
+        let width = (max_x - min_x + 1) as usize;
+
+        let mut cave = Self {
+            min_x,
+            width,
+            max_y,
+            floor,
+            blocked: vec![false; width * (floor + 1) as usize],
+        };
+
+        for &rock in rocks {
+            cave.block(rock);
+        }
+
+        for x in min_x..=max_x {
+            cave.block((x, floor));
+        }
+
+        cave
+    }
+
+    fn index(&self, (x, y): (i32, i32)) -> usize {
+        y as usize * self.width + (x - self.min_x) as usize
+    }
+
+    fn is_blocked(&self, point: (i32, i32)) -> bool {
+        self.blocked[self.index(point)]
+    }
+
+    fn block(&mut self, point: (i32, i32)) {
+        let index = self.index(point);
+        self.blocked[index] = true;
+    }
+
+    fn rocks(&self) -> HashSet<(i32, i32)> {
+        (0..self.floor)
+            .flat_map(|y| (0..self.width as i32).map(move |dx| (self.min_x + dx, y)))
+            .filter(|&point| self.is_blocked(point))
+            .collect()
+    }
+
+    /// Pours sand until the source is covered, or until a grain falls past the lowest rock if
+    /// `abyss` is set. The path of the falling grain is kept on a stack, so each new grain
+    /// resumes from where the previous one came to rest.
+    fn pour(&mut self, abyss: bool) -> usize {
+        let mut path = vec![SOURCE];
+        let mut count = 0;
+
+        while let Some(&(x, y)) = path.last() {
+            if abyss && y > self.max_y {
+                break
+            }
+
+            match [0, -1, 1].into_iter().map(|dx| (x + dx, y + 1)).find(|&p| !self.is_blocked(p)) {
+                Some(next) => path.push(next),
+                None => {
+                    self.block((x, y));
+                    count += 1;
+                    path.pop();
+                }
+            }
+        }
+
+        count
+    }
+
+    /// Counts every cell reachable from the source, sweeping one row at a time.
+    fn flood(&self) -> usize {
+        let mut row = vec![false; self.width];
+        row[(SOURCE.0 - self.min_x) as usize] = true;
+        let mut count = 1;
+
+        for y in SOURCE.1 + 1..self.floor {
+            let next: Vec<bool> = (0..self.width).map(|i| {
+                let from_above = row[i.saturating_sub(1)..(i + 2).min(self.width)].iter().any(|&b| b);
+                from_above && !self.blocked[y as usize * self.width + i]
+            }).collect();
+
+            count += next.iter().filter(|&&b| b).count();
+            row = next;
+        }
+
+        count
+    }
+}
+
 #[aoc_generator(day14)]
-fn parse(data: &str) -> (HashSet<(i32, i32)>, i32) {
-    let paths = paths(data);
-    let mut map = HashSet::new();
-    let mut max_y = 0;
+fn parse(data: &str) -> Result<Cave, ParseFailure> {
+    let data = normalize(data);
+
+    let paths = paths(&data)?;
+    let mut rocks = HashSet::new();
 
     for path in paths {
         for pair in path.windows(2) {
-            for point in range(pair[0], pair[1]) {
-                map.insert(point);
-                max_y = max_y.max(point.1)
-            }
+            rocks.extend(range(pair[0], pair[1]));
         }
     }
 
-    (map, max_y)
+    Ok(Cave::new(&rocks))
 }
 
 #[aoc(day14, part1)]
-fn part1((map, max_y): &(HashSet<(i32, i32)>, i32)) -> usize {
-    let mut map = map.clone();
+fn part1(cave: &Cave) -> usize {
+    cave.clone().pour(true)
+}
+
+#[aoc(day14, part2)]
+fn part2(cave: &Cave) -> usize {
+    cave.clone().pour(false)
+}
+
+#[aoc(day14, part2, Flood)]
+fn part2_flood(cave: &Cave) -> usize {
+    cave.flood()
+}
+
+#[aoc(day14, part1, HashSet)]
+fn part1_hashset(cave: &Cave) -> usize {
+    let (mut map, max_y) = (cave.rocks(), cave.max_y);
     let mut count = 0;
 
     loop {
-        let (mut x, mut y) = (500, 0);
+        let (mut x, mut y) = SOURCE;
 
         loop {
             if !map.contains(&(x, y + 1)) {
@@ -76,21 +193,20 @@ fn part1((map, max_y): &(HashSet<(i32, i32)>, i32)) -> usize {
                 break
             }
 
-            if y > *max_y {
+            if y > max_y {
                 return count
             }
         }
     }
 }
 
-#[aoc(day14, part2)]
-fn part2((map, max_y): &(HashSet<(i32, i32)>, i32)) -> usize {
-    let mut map = map.clone();
+#[aoc(day14, part2, HashSet)]
+fn part2_hashset(cave: &Cave) -> usize {
+    let (mut map, floor) = (cave.rocks(), cave.floor);
     let mut count = 0;
-    let floor = max_y + 2;
 
     loop {
-        let (mut x, mut y) = (500, 0);
+        let (mut x, mut y) = SOURCE;
 
         loop {
             if y + 1 == floor {
@@ -109,7 +225,7 @@ fn part2((map, max_y): &(HashSet<(i32, i32)>, i32)) -> usize {
                 map.insert((x, y));
                 count += 1;
 
-                if x == 500 && y == 0 {
+                if (x, y) == SOURCE {
                     return count
                 }
 
@@ -117,4 +233,78 @@ fn part2((map, max_y): &(HashSet<(i32, i32)>, i32)) -> usize {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "498,4 -> 498,6 -> 496,6\n503,4 -> 502,4 -> 502,9 -> 494,9";
+
+    fn assert_runners_agree(cave: &Cave) -> (usize, usize) {
+        let mut abyss = cave.clone();
+        let (part1, part2) = (abyss.pour(true), cave.clone().pour(false));
+
+        // The HashSet runner never stops if sand covers the source before any falls past the
+        // lowest rock, which puzzle inputs never do
+        if !abyss.is_blocked(SOURCE) {
+            assert_eq!(part1, part1_hashset(cave));
+        }
+        assert_eq!(part2, part2_hashset(cave));
+        assert_eq!(part2, cave.flood());
+
+        (part1, part2)
+    }
+
+    /// A cave of pseudo-random horizontal and vertical walls below the source.
+    fn generate(walls: usize, seed: u64) -> Cave {
+        let mut state = seed;
+        let mut roll = |n: i32| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) as i32 % n
+        };
+
+        let mut rocks = HashSet::new();
+        for _ in 0..walls {
+            let start = (SOURCE.0 - 20 + roll(41), 1 + roll(30));
+            let length = roll(8);
+            let end = if roll(2) == 0 { (start.0 + length, start.1) } else { (start.0, start.1 + length) };
+            rocks.extend(range(start, end));
+        }
+
+        Cave::new(&rocks)
+    }
+
+    #[test]
+    fn example_agrees() {
+        assert_eq!(assert_runners_agree(&parse(EXAMPLE).unwrap()), (24, 93));
+    }
+
+    #[test]
+    fn generated_caves_agree() {
+        for seed in 0..20 {
+            assert_runners_agree(&generate(25, seed));
+        }
+    }
+
+    #[test]
+    fn empty_cave_agrees() {
+        assert_eq!(assert_runners_agree(&Cave::new(&HashSet::new())), (0, 4));
+    }
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((part1(&parsed), part2(&parsed)), (24, 93), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("498,4 -> 498\n503,4 -> 502,4"), &[("Unexpected token, expected one of: ,", 12..13)]);
+        assert_fails(parse("498,4 498,6"), &[("Unexpected token, expected one of: -", 6..7)]);
+    }
+}
@@ -1,9 +1,7 @@
-use std::ops::Range;
-
 use aoc_runner_derive::{aoc, aoc_generator};
-use btree_range_map::RangeSet;
+use chumsky::prelude::*;
 
-use crate::parse::ints;
+use crate::{interval::{Interval, IntervalSet}, parse::{lines, normalize, num, input_name, parse_input, ParseFailure}};
 
 struct Sensor {
     sensor: (i64, i64),
@@ -12,13 +10,9 @@ struct Sensor {
 }
 
 impl Sensor {
-    fn intersect_row(&self, y: i64) -> (i64, i64) {
+    fn intersect_row(&self, y: i64) -> Interval {
         let diff = self.radius - (self.sensor.1 - y).abs();
-        if diff < 0 {
-            (0, 0)
-        } else {
-            (self.sensor.0 - diff, self.sensor.0 + diff + 1)
-        }
+        Interval::inclusive(self.sensor.0 - diff, self.sensor.0 + diff)
     }
 
     fn intersect_point(&self, point: (i64, i64)) -> bool {
@@ -30,12 +24,10 @@ impl Sensor {
     }
 }
 
-impl From<Vec<i64>> for Sensor {
-    fn from(data: Vec<i64>) -> Self {
-        assert_eq!(data.len(), 4);
-
-        let sensor = (data[0], data[1]);
-        let beacon = (data[2], data[3]);
+impl From<[i64; 4]> for Sensor {
+    fn from([sx, sy, bx, by]: [i64; 4]) -> Self {
+        let sensor = (sx, sy);
+        let beacon = (bx, by);
         let radius = Self::dist(sensor, beacon);
 
         Self {
@@ -46,53 +38,63 @@ impl From<Vec<i64>> for Sensor {
     }
 }
 
+fn report() -> impl Parser<char, Vec<Sensor>, Error = Simple<char>> {
+    let point = just("x=").ignore_then(num()).then_ignore(just(", y=")).then(num());
+    let sensor = just("Sensor at ").ignore_then(point.clone())
+        .then_ignore(just(": closest beacon is at ")).then(point)
+        .map(|((sx, sy), (bx, by))| Sensor::from([sx, sy, bx, by]));
+
+    lines(sensor)
+}
+
 #[aoc_generator(day15)]
-fn parse(data: &str) -> Vec<Sensor> {
-    data.lines().map(ints).map(Sensor::from).collect()
+fn parse(data: &str) -> Result<Vec<Sensor>, ParseFailure> {
+    let data = normalize(data);
+
+    parse_input(report(), &data, &input_name(15))
 }
 
 const ROW: i64 = 2000000;
 
-#[aoc(day15, part1)]
-fn part1(data: &Vec<Sensor>) -> u64 {
-    let mut rset = RangeSet::new();
+/// The positions in row `y` that can't hold a beacon.
+fn covered(data: &[Sensor], y: i64) -> u64 {
+    let mut rset = IntervalSet::new();
     let mut beacons = Vec::new();
 
     for sensor in data {
-        let (rx, ry) = sensor.intersect_row(ROW);
-        rset.insert(rx..ry);
+        rset.insert(sensor.intersect_row(y));
 
-        if sensor.beacon.1 == ROW {
+        if sensor.beacon.1 == y {
             beacons.push(sensor.beacon.0);
         }
     }
 
     for bx in beacons {
-        rset.remove(bx)
+        rset.remove(Interval::inclusive(bx, bx))
     }
 
     rset.len()
 }
 
-const SIZE: i64 = 4000000;
-
-fn split(range: Range<i64>) -> (Range<i64>, Range<i64>) {
-    let mid = range.start + (range.end - range.start) / 2;
-    (range.start..mid, mid..range.end)
+#[aoc(day15, part1)]
+fn part1(data: &[Sensor]) -> u64 {
+    covered(data, ROW)
 }
 
-fn quadrants(x_range: Range<i64>, y_range: Range<i64>) -> [(Range<i64>, Range<i64>); 4] {
-    let (x1, x2) = split(x_range);
-    let (y1, y2) = split(y_range);
+const SIZE: i64 = 4000000;
+
+fn quadrants(x_range: Interval, y_range: Interval) -> [(Interval, Interval); 4] {
+    let (x1, x2) = x_range.split();
+    let (y1, y2) = y_range.split();
     [
-        (x1.clone(), y1.clone()),
-        (x2.clone(), y1),
-        (x1, y2.clone()),
+        (x1, y1),
+        (x2, y1),
+        (x1, y2),
         (x2, y2),
     ]
 }
 
-fn corners(x_range: &Range<i64>, y_range: &Range<i64>) -> [(i64, i64); 4] {
+fn corners(x_range: &Interval, y_range: &Interval) -> [(i64, i64); 4] {
     [
         (x_range.start, y_range.start),
         (x_range.start, y_range.end - 1),
@@ -101,12 +103,12 @@ fn corners(x_range: &Range<i64>, y_range: &Range<i64>) -> [(i64, i64); 4] {
     ]
 }
 
-fn find_beacon(data: &Vec<Sensor>, x_range: Range<i64>, y_range: Range<i64>) -> Option<(i64, i64)> {
+fn find_beacon(data: &[Sensor], x_range: Interval, y_range: Interval) -> Option<(i64, i64)> {
     if x_range.is_empty() || y_range.is_empty() {
         return None
     }
 
-    if x_range.end - x_range.start == 1 && y_range.end - y_range.start == 1 {
+    if x_range.len() == 1 && y_range.len() == 1 {
         let point = (x_range.start, y_range.start);
         if data.iter().any(|sensor| sensor.intersect_point(point)) {
             return None
@@ -126,8 +128,50 @@ fn find_beacon(data: &Vec<Sensor>, x_range: Range<i64>, y_range: Range<i64>) ->
     })
 }
 
-#[aoc(day15, part2)]
-fn part2_b(data: &Vec<Sensor>) -> i64 {
-    let (x, y) = find_beacon(data, 0..SIZE, 0..SIZE).unwrap();
+/// The tuning frequency of the one position within `0..size` on both axes that no sensor covers.
+fn tuning_frequency(data: &[Sensor], size: i64) -> i64 {
+    let (x, y) = find_beacon(data, (0..size).into(), (0..size).into()).unwrap();
     x * SIZE + y
-}
\ No newline at end of file
+}
+
+#[aoc(day15, part2)]
+fn part2_b(data: &[Sensor]) -> i64 {
+    tuning_frequency(data, SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "\
+Sensor at x=2, y=18: closest beacon is at x=-2, y=15
+Sensor at x=9, y=16: closest beacon is at x=10, y=16
+Sensor at x=13, y=2: closest beacon is at x=15, y=3
+Sensor at x=12, y=14: closest beacon is at x=10, y=16
+Sensor at x=10, y=20: closest beacon is at x=10, y=16
+Sensor at x=14, y=17: closest beacon is at x=10, y=16
+Sensor at x=8, y=7: closest beacon is at x=2, y=10
+Sensor at x=2, y=0: closest beacon is at x=2, y=10
+Sensor at x=0, y=11: closest beacon is at x=2, y=10
+Sensor at x=20, y=14: closest beacon is at x=25, y=17
+Sensor at x=17, y=20: closest beacon is at x=21, y=22
+Sensor at x=16, y=7: closest beacon is at x=15, y=3
+Sensor at x=14, y=3: closest beacon is at x=15, y=3
+Sensor at x=20, y=1: closest beacon is at x=15, y=3";
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((covered(&parsed, 10), tuning_frequency(&parsed, 21)), (26, 56000011), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("Sensor at x=2, y=18: closest beacon is at x=-2"), &[("Unexpected end of input, expected one of: ,", 46..46)]);
+        assert_fails(parse("Sensor at x=2, y=18: closest beacon is at x=-2, y=1x"), &[("Unexpected token, expected one of: end of input, \\n", 51..52)]);
+    }
+}
@@ -1,40 +1,66 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use aoc_runner_derive::{aoc, aoc_generator};
+use chumsky::prelude::*;
 use petgraph::{Graph, Directed, graph::NodeIndex, algo::dijkstra, Direction, visit::EdgeRef};
-use regex::Regex;
+
+use crate::parse::{lines, normalize, num, input_name, parse_input, ParseFailure};
 
 type VGraph = Graph::<u32, u32, Directed>;
 
+type Valve = (String, u32, Vec<String>);
+
+/// Every tunnel must lead to a valve in the scan, and there must be a valve `AA` to start from.
+fn scan() -> impl Parser<char, Vec<Valve>, Error = Simple<char>> {
+    let name = filter(char::is_ascii_uppercase).labelled("valve name").repeated().exactly(2).collect::<String>();
+    let tunnels = choice((just("; tunnels lead to valves "), just("; tunnel leads to valve ")));
+
+    let valve = just("Valve ").ignore_then(name)
+        .then_ignore(just(" has flow rate=")).then(num())
+        .then_ignore(tunnels)
+        .then(name.map_with_span(|name, span| (name, span)).separated_by(just(", ")).at_least(1));
+
+    lines(valve).validate(|valves, span, emit| {
+        let names: HashSet<&str> = valves.iter().map(|((name, _), _)| name.as_str()).collect();
+
+        if !names.contains("AA") {
+            emit(Simple::custom(span, "there is no valve AA to start from"))
+        }
+
+        for (_, edges) in &valves {
+            for (edge, span) in edges {
+                if !names.contains(edge.as_str()) {
+                    emit(Simple::custom(span.clone(), format!("there is no valve {}", edge)))
+                }
+            }
+        }
+
+        valves.into_iter()
+            .map(|((name, rate), edges)| (name, rate, edges.into_iter().map(|(edge, _)| edge).collect()))
+            .collect()
+    })
+}
+
 #[aoc_generator(day16)]
-fn parse(data: &str) -> (NodeIndex, VGraph) {
-    let re = Regex::new(r"Valve ([A-Z]{2}) has flow rate=(\d+); tunnels? leads? to valves? (([A-Z]{2}, )*[A-Z]{2})").unwrap();
-    let mut lines = Vec::new();
-
-    for line in data.lines() {
-        let captures = re.captures(line).unwrap();
-        let name = captures.get(1).unwrap().as_str();
-        let rate = captures.get(2).unwrap().as_str().parse().unwrap();
-        let edges = captures.get(3).unwrap().as_str().split(", ").collect();
-        lines.push((name, rate, edges));
-    }
+fn parse(data: &str) -> Result<(NodeIndex, VGraph), ParseFailure> {
+    let data = normalize(data);
 
-    create_graph(lines)
+    Ok(create_graph(&parse_input(scan(), &data, &input_name(16))?))
 }
 
-fn create_graph(data: Vec<(&str, u32, Vec<&str>)>) -> (NodeIndex, VGraph) {
+fn create_graph(data: &[Valve]) -> (NodeIndex, VGraph) {
     let mut index = HashMap::new();
     let mut graph = Graph::new();
 
-    for (name, rate, _) in &data {
+    for (name, rate, _) in data {
         let node = graph.add_node(*rate);
-        index.insert(*name, node);
+        index.insert(name.as_str(), node);
     }
 
     for (name, _, edges) in data {
-        let node = index[name];
+        let node = index[name.as_str()];
         for edge in edges {
-            graph.add_edge(node, index[edge], 1);
+            graph.add_edge(node, index[edge.as_str()], 1);
         }
     }
 
@@ -119,4 +145,38 @@ fn part2((root_node, graph): &(NodeIndex, VGraph)) -> u32 {
         })
         .max()
         .unwrap()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "\
+Valve AA has flow rate=0; tunnels lead to valves DD, II, BB
+Valve BB has flow rate=13; tunnels lead to valves CC, AA
+Valve CC has flow rate=2; tunnels lead to valves DD, BB
+Valve DD has flow rate=20; tunnels lead to valves CC, AA, EE
+Valve EE has flow rate=3; tunnels lead to valves FF, DD
+Valve FF has flow rate=0; tunnels lead to valves EE, GG
+Valve GG has flow rate=0; tunnels lead to valves FF, HH
+Valve HH has flow rate=22; tunnel leads to valve GG
+Valve II has flow rate=0; tunnels lead to valves AA, JJ
+Valve JJ has flow rate=21; tunnel leads to valve II";
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((part1(&parsed), part2(&parsed)), (1651, 1707), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("Valve AA has flow rate=0; tunnels lead to valves BB\nValve BB has flow rate=13; tunnel leads to valve CC"), &[("there is no valve CC", 101..103)]);
+        assert_fails(parse("Valve BB has flow rate=13; tunnel leads to valve BB"), &[("there is no valve AA to start from", 0..51)]);
+        assert_fails(parse("Valve AA has flow rate=x; tunnel leads to valve AA"), &[("Unexpected token, expected one of: number", 23..24)]);
+    }
+}
@@ -1,124 +1,362 @@
-use std::cmp::Ordering;
+use std::fmt;
 
 use aoc_runner_derive::{aoc, aoc_generator};
-use parse_display::{FromStr, Display, ParseError};
+use chumsky::prelude::*;
+use parse_display::{FromStr, Display};
 
-#[derive(Display, FromStr, Eq, PartialEq, Copy, Clone)]
-enum Hand {
-    #[from_str(regex = "A|X")]
-    Rock,
-    #[from_str(regex = "B|Y")]
-    Paper,
-    #[from_str(regex = "C|Z")]
-    Scissors
-}
+use crate::parse::{lines, normalize, input_name, parse_input, ParseFailure};
 
+/// A line of the strategy guide: the opponent's letter, then ours.
+#[derive(Display, FromStr, Clone, Copy)]
+#[display("{opponent} {me}")]
+pub struct Line {
+    pub opponent: char,
+    pub me: char,
+}
 
-#[derive(Display, FromStr, Copy, Clone)]
-enum Outcome {
-    #[display("X")]
+#[derive(Display, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
     Loss,
-    #[display("Y")]
     Draw,
-    #[display("Z")]
     Win
 }
 
-impl Ord for Hand {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match (self, other) {
-            (Hand::Rock, Hand::Rock) => Ordering::Equal,
-            (Hand::Rock, Hand::Paper) => Ordering::Less,
-            (Hand::Rock, Hand::Scissors) => Ordering::Greater,
-            (Hand::Paper, Hand::Rock) => Ordering::Greater,
-            (Hand::Paper, Hand::Paper) => Ordering::Equal,
-            (Hand::Paper, Hand::Scissors) => Ordering::Less,
-            (Hand::Scissors, Hand::Rock) => Ordering::Less,
-            (Hand::Scissors, Hand::Paper) => Ordering::Greater,
-            (Hand::Scissors, Hand::Scissors) => Ordering::Equal,
-        }
-    }
+const OUTCOMES: [Outcome; 3] = [Outcome::Loss, Outcome::Draw, Outcome::Win];
+
+#[derive(Clone, Debug)]
+pub struct Shape {
+    pub name: String,
+    pub points: u32,
 }
 
-impl PartialOrd for Hand {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        return Some(self.cmp(other))
-    }
+#[derive(Display, Debug, PartialEq, Eq)]
+pub enum RuleError {
+    #[display("a fair tournament needs an odd number of shapes, not {0}")]
+    EvenShapes(usize),
+    #[display("expected {expected} letters for {what}, found {found}")]
+    Letters { what: &'static str, expected: usize, found: usize },
+    #[display("line {line}: unknown letter {letter:?}")]
+    UnknownLetter { line: usize, letter: char },
+    #[display("letter {letter:?} is used twice for {what}")]
+    DuplicateLetter { what: &'static str, letter: char },
 }
 
-#[derive(Display, FromStr)]
-#[display("{oponent} {me}")]
-struct Round {
-    oponent: Hand,
-    me: Hand
+impl std::error::Error for RuleError {}
+
+/// A cyclic tournament. Each shape beats the half of the other shapes that come before it in
+/// `shapes`, wrapping around, and loses to the half that come after it.
+#[derive(Clone, Debug)]
+pub struct Rules {
+    shapes: Vec<Shape>,
+    /// Points for a loss, a draw and a win.
+    outcome_points: [u32; 3],
+    /// The letters for the opponent's shapes, in the same order as `shapes`.
+    opponent: Vec<char>,
+    /// The letters for our shapes, in the same order as `shapes`.
+    me: Vec<char>,
+    /// The letters for a loss, a draw and a win.
+    outcomes: [char; 3],
 }
 
-impl From<&Strategy> for Round {
-    fn from(strategy: &Strategy) -> Self {
-        let hand = match (strategy.oponent, strategy.outcome) {
-            (Hand::Rock, Outcome::Loss) => Hand::Scissors,
-            (Hand::Rock, Outcome::Draw) => Hand::Rock,
-            (Hand::Rock, Outcome::Win) => Hand::Paper,
-            (Hand::Paper, Outcome::Loss) => Hand::Rock,
-            (Hand::Paper, Outcome::Draw) => Hand::Paper,
-            (Hand::Paper, Outcome::Win) => Hand::Scissors,
-            (Hand::Scissors, Outcome::Loss) => Hand::Paper,
-            (Hand::Scissors, Outcome::Draw) => Hand::Scissors,
-            (Hand::Scissors, Outcome::Win) => Hand::Rock,
-        };
+impl Rules {
+    pub fn new(shapes: Vec<Shape>, outcome_points: [u32; 3], opponent: Vec<char>, me: Vec<char>, outcomes: [char; 3]) -> Result<Self, RuleError> {
+        if shapes.len().is_multiple_of(2) {
+            return Err(RuleError::EvenShapes(shapes.len()))
+        }
+
+        for (what, letters) in [("the opponent", &opponent), ("us", &me)] {
+            if letters.len() != shapes.len() {
+                return Err(RuleError::Letters { what, expected: shapes.len(), found: letters.len() })
+            }
+        }
 
-        Round {
-            oponent: strategy.oponent,
-            me: hand
+        // `lookup` takes the first match, so a repeated letter would hide a shape or outcome.
+        for (what, letters) in [("the opponent", &opponent[..]), ("us", &me[..]), ("outcomes", &outcomes[..])] {
+            if let Some((_, &letter)) = letters.iter().enumerate().find(|&(i, l)| letters[..i].contains(l)) {
+                return Err(RuleError::DuplicateLetter { what, letter })
+            }
         }
+
+        Ok(Self { shapes, outcome_points, opponent, me, outcomes })
+    }
+
+    pub fn puzzle() -> Self {
+        let shapes = [("Rock", 1), ("Paper", 2), ("Scissors", 3)]
+            .map(|(name, points)| Shape { name: name.to_string(), points });
+
+        Self::new(shapes.to_vec(), [0, 3, 6], vec!['A', 'B', 'C'], vec!['X', 'Y', 'Z'], ['X', 'Y', 'Z']).unwrap()
+    }
+
+    pub fn shapes(&self) -> &[Shape] {
+        &self.shapes
+    }
+
+    pub fn outcome(&self, me: usize, opponent: usize) -> Outcome {
+        let n = self.shapes.len();
+        match (me + n - opponent) % n {
+            0 => Outcome::Draw,
+            diff if diff <= n / 2 => Outcome::Win,
+            _ => Outcome::Loss,
+        }
+    }
+
+    pub fn points(&self, me: usize, opponent: usize) -> u32 {
+        self.shapes[me].points + self.outcome_points[self.outcome(me, opponent) as usize]
+    }
+
+    /// The shape that gives `outcome` against `opponent`. With more than three shapes there
+    /// are several, so the one worth the most points is chosen.
+    pub fn shape_for(&self, opponent: usize, outcome: Outcome) -> usize {
+        (0..self.shapes.len())
+            .filter(|&me| self.outcome(me, opponent) == outcome)
+            .max_by_key(|&me| self.shapes[me].points)
+            .unwrap()
+    }
+
+    fn lookup(letters: &[char], line: usize, letter: char) -> Result<usize, RuleError> {
+        letters.iter().position(|&l| l == letter).ok_or(RuleError::UnknownLetter { line, letter })
+    }
+
+    /// Scores the guide reading our letter as the shape to play.
+    pub fn score_shapes(&self, guide: &[Line]) -> Result<u32, RuleError> {
+        guide.iter().enumerate().map(|(i, line)| {
+            let opponent = Self::lookup(&self.opponent, i + 1, line.opponent)?;
+            let me = Self::lookup(&self.me, i + 1, line.me)?;
+            Ok(self.points(me, opponent))
+        }).sum()
+    }
+
+    /// Scores the guide reading our letter as the outcome to aim for.
+    pub fn score_outcomes(&self, guide: &[Line]) -> Result<u32, RuleError> {
+        guide.iter().enumerate().map(|(i, line)| {
+            let opponent = Self::lookup(&self.opponent, i + 1, line.opponent)?;
+            let outcome = OUTCOMES[Self::lookup(&self.outcomes, i + 1, line.me)?];
+            Ok(self.points(self.shape_for(opponent, outcome), opponent))
+        }).sum()
     }
 }
 
-#[derive(Display, FromStr)]
-#[display("{oponent} {outcome}")]
-struct Strategy {
-    oponent: Hand,
-    outcome: Outcome
+/// One way of reading our letters, and the score per round it would get on average.
+#[derive(Clone, Debug)]
+pub struct Mapping {
+    pub letters: Vec<(char, String)>,
+    pub expected: f64,
 }
 
-impl Round {
-    fn outcome_points(&self) -> u32 {
-        match self.me.cmp(&self.oponent) {
-            Ordering::Less => 0,
-            Ordering::Equal => 3,
-            Ordering::Greater => 6,
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (letter, meaning) in &self.letters {
+            write!(f, "{}={} ", letter, meaning)?;
         }
+        write!(f, "({:.3} per round)", self.expected)
     }
+}
+
+/// How a guide scores under every reading of our letters, best first.
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    pub opponent: Vec<(char, f64)>,
+    pub as_shapes: Vec<Mapping>,
+    pub as_outcomes: Vec<Mapping>,
+}
 
-    fn shape_points(&self) -> u32 {
-        match self.me {
-            Hand::Rock => 1,
-            Hand::Paper => 2,
-            Hand::Scissors => 3,
+impl fmt::Display for Analysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "opponent plays")?;
+        for (letter, frequency) in &self.opponent {
+            write!(f, " {}={:.1}%", letter, frequency * 100.0)?;
         }
+        writeln!(f)?;
+
+        for (kind, mappings) in [("shapes", &self.as_shapes), ("outcomes", &self.as_outcomes)] {
+            if let (Some(best), Some(worst)) = (mappings.first(), mappings.last()) {
+                writeln!(f, "best as {}: {}", kind, best)?;
+                writeln!(f, "worst as {}: {}", kind, worst)?;
+            }
+        }
+        Ok(())
     }
+}
 
-    fn points(&self) -> u32 {
-        self.shape_points() + self.outcome_points()
+/// Every ordering of `0..n`.
+fn permutations(n: usize) -> Vec<Vec<usize>> {
+    if n == 0 {
+        return vec![vec![]]
     }
+
+    permutations(n - 1).into_iter().flat_map(|perm| {
+        (0..n).map(move |i| {
+            let mut perm = perm.clone();
+            perm.insert(i, n - 1);
+            perm
+        })
+    }).collect()
 }
 
-#[aoc_generator(day2, part1)]
-fn parse_part1(data: &str) -> Result<Vec<Round>, ParseError> {
-    data.lines().map(str::parse).collect()
+impl Rules {
+    /// Tries every one-to-one reading of our letters, first as shapes and then as outcomes,
+    /// scoring each against the rounds in `guide`.
+    pub fn analyse(&self, guide: &[Line]) -> Result<Analysis, RuleError> {
+        let rounds = guide.len().max(1) as f64;
+
+        let opponent = self.opponent.iter()
+            .map(|&letter| (letter, guide.iter().filter(|line| line.opponent == letter).count() as f64 / rounds))
+            .collect();
+
+        let mut as_shapes = permutations(self.me.len()).into_iter().map(|perm| {
+            let mut rules = self.clone();
+            for (i, &shape) in perm.iter().enumerate() {
+                rules.me[shape] = self.me[i];
+            }
+
+            Ok(Mapping {
+                letters: perm.iter().enumerate().map(|(i, &shape)| (self.me[i], self.shapes[shape].name.clone())).collect(),
+                expected: rules.score_shapes(guide)? as f64 / rounds,
+            })
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        let mut as_outcomes = permutations(OUTCOMES.len()).into_iter().map(|perm| {
+            let mut rules = self.clone();
+            for (i, &outcome) in perm.iter().enumerate() {
+                rules.outcomes[outcome] = self.outcomes[i];
+            }
+
+            Ok(Mapping {
+                letters: perm.iter().enumerate().map(|(i, &outcome)| (self.outcomes[i], OUTCOMES[outcome].to_string())).collect(),
+                expected: rules.score_outcomes(guide)? as f64 / rounds,
+            })
+        }).collect::<Result<Vec<_>, _>>()?;
+
+        as_shapes.sort_by(|a, b| b.expected.total_cmp(&a.expected));
+        as_outcomes.sort_by(|a, b| b.expected.total_cmp(&a.expected));
+
+        Ok(Analysis { opponent, as_shapes, as_outcomes })
+    }
 }
 
-#[aoc(day2, part1)]
-fn part1(data: &Vec<Round>) -> u32 {
-    data.iter().map(Round::points).sum()
+/// Any character other than whitespace can be a letter, and the rules decide what it means.
+fn guide() -> impl Parser<char, Vec<Line>, Error = Simple<char>> {
+    let letter = filter(|c: &char| !c.is_whitespace()).labelled("letter");
+    let line = letter.then_ignore(just(' ')).then(letter)
+        .map(|(opponent, me)| Line { opponent, me });
+
+    lines(line)
 }
 
-#[aoc_generator(day2, part2)]
-fn parse_part2(data: &str) -> Result<Vec<Strategy>, ParseError> {
-    data.lines().map(str::parse).collect()
+#[aoc_generator(day2)]
+fn parse(data: &str) -> Result<Vec<Line>, ParseFailure> {
+    let data = normalize(data);
+
+    parse_input(guide(), &data, &input_name(2))
+}
+
+#[aoc(day2, part1)]
+fn part1(data: &[Line]) -> Result<u32, RuleError> {
+    Rules::puzzle().score_shapes(data)
 }
 
 #[aoc(day2, part2)]
-fn part2(data: &Vec<Strategy>) -> u32 {
-    data.iter().map(Round::from).map(|r| r.points()).sum()
-}
\ No newline at end of file
+fn part2(data: &[Line]) -> Result<u32, RuleError> {
+    Rules::puzzle().score_outcomes(data)
+}
+
+#[aoc(day2, part2, Analysis)]
+fn part2_analysis(data: &[Line]) -> Result<Analysis, RuleError> {
+    Rules::puzzle().analyse(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "A Y\nB X\nC Z";
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((part1(&parsed).unwrap(), part2(&parsed).unwrap()), (15, 12), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("A Y\nBX"), &[("Unexpected token, expected one of:  ", 5..6)]);
+        assert_fails(parse("A Y\nB "), &[("Unexpected end of input, expected one of:  ", 5..5)]);
+    }
+
+    fn shapes(n: u32) -> Vec<Shape> {
+        (1..=n).map(|points| Shape { name: format!("S{}", points), points }).collect()
+    }
+
+    fn guide(lines: &[(char, char)]) -> Vec<Line> {
+        lines.iter().map(|&(opponent, me)| Line { opponent, me }).collect()
+    }
+
+    #[test]
+    fn five_shapes_with_custom_points() {
+        let rules = Rules::new(shapes(5), [1, 2, 10], vec!['A', 'B', 'C', 'D', 'E'], vec!['V', 'W', 'X', 'Y', 'Z'], ['L', 'D', 'W']).unwrap();
+
+        // Each shape beats the two before it and loses to the two after it.
+        assert_eq!((0..5).map(|me| rules.outcome(me, 2)).collect::<Vec<_>>(),
+            [Outcome::Loss, Outcome::Loss, Outcome::Draw, Outcome::Win, Outcome::Win]);
+
+        let played = guide(&[('A', 'X'), ('C', 'V'), ('E', 'Z'), ('B', 'Z')]);
+        assert_eq!(rules.score_shapes(&played), Ok(13 + 2 + 7 + 6));
+
+        // Two shapes give each outcome, and the one worth more points is played.
+        let aimed = guide(&[('A', 'W'), ('C', 'L'), ('E', 'D')]);
+        assert_eq!(rules.score_outcomes(&aimed), Ok(13 + 3 + 7));
+    }
+
+    #[test]
+    fn bad_rules_are_rejected() {
+        let letters = || vec!['A', 'B', 'C'];
+        let new = |shapes, opponent, me, outcomes| Rules::new(shapes, [0, 3, 6], opponent, me, outcomes).map(|_| ());
+
+        assert_eq!(new(shapes(4), vec!['A', 'B', 'C', 'D'], vec!['W', 'X', 'Y', 'Z'], ['X', 'Y', 'Z']),
+            Err(RuleError::EvenShapes(4)));
+        assert_eq!(new(shapes(3), letters(), vec!['X', 'Y'], ['X', 'Y', 'Z']),
+            Err(RuleError::Letters { what: "us", expected: 3, found: 2 }));
+        assert_eq!(new(shapes(3), vec!['A', 'B', 'C', 'D'], vec!['X', 'Y', 'Z'], ['X', 'Y', 'Z']),
+            Err(RuleError::Letters { what: "the opponent", expected: 3, found: 4 }));
+        assert_eq!(new(shapes(3), vec!['A', 'B', 'A'], vec!['X', 'Y', 'Z'], ['X', 'Y', 'Z']),
+            Err(RuleError::DuplicateLetter { what: "the opponent", letter: 'A' }));
+        assert_eq!(new(shapes(3), letters(), vec!['X', 'Y', 'Z'], ['X', 'Z', 'Z']),
+            Err(RuleError::DuplicateLetter { what: "outcomes", letter: 'Z' }));
+    }
+
+    #[test]
+    fn unknown_letters_are_numbered_by_line() {
+        let rules = Rules::puzzle();
+
+        assert_eq!(rules.score_shapes(&guide(&[('A', 'Y'), ('B', 'Q')])), Err(RuleError::UnknownLetter { line: 2, letter: 'Q' }));
+        assert_eq!(rules.score_outcomes(&guide(&[('A', 'Y'), ('B', 'X'), ('D', 'Z')])), Err(RuleError::UnknownLetter { line: 3, letter: 'D' }));
+        assert_eq!(RuleError::UnknownLetter { line: 2, letter: 'Q' }.to_string(), "line 2: unknown letter 'Q'");
+    }
+
+    #[test]
+    fn analysis_includes_the_puzzle_readings() {
+        let analysis = part2_analysis(&parse(EXAMPLE).unwrap()).unwrap();
+        let reading = |mappings: &[Mapping], meanings: [&str; 3]| {
+            mappings.iter()
+                .find(|mapping| mapping.letters.iter().map(|(_, meaning)| meaning.as_str()).eq(meanings))
+                .map(|mapping| mapping.expected * 3.0)
+        };
+
+        assert_eq!(analysis.opponent, [('A', 1.0 / 3.0), ('B', 1.0 / 3.0), ('C', 1.0 / 3.0)]);
+        assert_eq!(analysis.as_shapes.len(), 6);
+        assert_eq!(analysis.as_outcomes.len(), 6);
+        assert!(analysis.as_shapes.iter().chain(&analysis.as_outcomes).all(|mapping| mapping.letters.iter().map(|&(letter, _)| letter).eq(['X', 'Y', 'Z'])));
+
+        assert_eq!(reading(&analysis.as_shapes, ["Rock", "Paper", "Scissors"]), Some(15.0));
+        assert_eq!(reading(&analysis.as_outcomes, ["Loss", "Draw", "Win"]), Some(12.0));
+
+        for mappings in [&analysis.as_shapes, &analysis.as_outcomes] {
+            assert!(mappings.windows(2).all(|pair| pair[0].expected >= pair[1].expected));
+        }
+        assert!(analysis.to_string().starts_with("opponent plays A=33.3% B=33.3% C=33.3%\nbest as shapes: "));
+    }
+}
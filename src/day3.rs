@@ -1,33 +1,144 @@
-use std::ops::BitAnd;
+use std::collections::BTreeSet;
 
-use aoc_runner_derive::aoc;
+use aoc_runner_derive::{aoc, aoc_generator};
+use chumsky::prelude::*;
+use parse_display::Display;
 
-fn priority(c: u8) -> u8 {
+use crate::parse::{lines, normalize, input_name, parse_input, ParseFailure};
+
+#[derive(Display, Debug, PartialEq, Eq)]
+pub enum RucksackError {
+    #[display("rucksack {0} can't be split into two equal compartments")]
+    OddRucksack(usize),
+    #[display("group {group} has only {len} rucksacks")]
+    RaggedGroup { group: usize, len: usize },
+    #[display("groups must have at least one rucksack")]
+    EmptyGroups,
+    #[display("{0} shares no item")]
+    NoneShared(String),
+    #[display("{0} shares more than one item: {1}")]
+    SeveralShared(String, String),
+    #[display("item {0:?} has no priority")]
+    NoPriority(char),
+}
+
+impl std::error::Error for RucksackError {}
+
+/// The puzzle's priorities: `a` to `z` are 1 to 26, and `A` to `Z` are 27 to 52.
+pub fn priority(c: char) -> Option<u32> {
     match c {
-        b'a'..=b'z' => c - b'a' + 1,
-        b'A'..=b'Z' => c - b'A' + 27,
-        _ => panic!("outside range: {}", c)
+        'a'..='z' => Some(c as u32 - 'a' as u32 + 1),
+        'A'..='Z' => Some(c as u32 - 'A' as u32 + 27),
+        _ => None
+    }
+}
+
+/// The one item found in every one of `parts`.
+fn shared<'a>(name: impl Fn() -> String, parts: impl IntoIterator<Item=&'a str>) -> Result<char, RucksackError> {
+    let common = parts.into_iter()
+        .map(|part| part.chars().collect::<BTreeSet<_>>())
+        .reduce(|a, b| &a & &b)
+        .unwrap_or_default();
+
+    let mut items = common.iter();
+    match (items.next(), items.next()) {
+        (Some(&item), None) => Ok(item),
+        (None, _) => Err(RucksackError::NoneShared(name())),
+        _ => Err(RucksackError::SeveralShared(name(), common.into_iter().collect())),
+    }
+}
+
+/// The item shared between both compartments of each rucksack.
+pub fn shared_by_compartments(data: &str) -> Result<Vec<char>, RucksackError> {
+    data.lines().enumerate().map(|(i, line)| {
+        let chars: Vec<(usize, char)> = line.char_indices().collect();
+        if !chars.len().is_multiple_of(2) {
+            return Err(RucksackError::OddRucksack(i + 1))
+        }
+
+        let (left, right) = line.split_at(chars.get(chars.len() / 2).map_or(0, |&(at, _)| at));
+        shared(|| format!("rucksack {}", i + 1), [left, right])
+    }).collect()
+}
+
+/// The item shared by every rucksack in each group of `size`. A final group with fewer
+/// rucksacks, or a `size` of 0, is an error.
+pub fn shared_by_groups(data: &str, size: usize) -> Result<Vec<char>, RucksackError> {
+    if size == 0 {
+        return Err(RucksackError::EmptyGroups)
     }
+
+    let lines: Vec<_> = data.lines().collect();
+
+    lines.chunks(size).enumerate().map(|(i, group)| {
+        if group.len() < size {
+            return Err(RucksackError::RaggedGroup { group: i + 1, len: group.len() })
+        }
+
+        shared(|| format!("group {}", i + 1), group.iter().copied())
+    }).collect()
 }
 
-fn bitmap(data: impl AsRef<[u8]>) -> u64 {
-    data.as_ref().iter().cloned().fold(0, |map, c| {
-        map | 1 << priority(c)
-    })
+pub fn priority_sum(items: &[char], priority: impl Fn(char) -> Option<u32>) -> Result<u32, RucksackError> {
+    items.iter().map(|&item| priority(item).ok_or(RucksackError::NoPriority(item))).sum()
+}
+
+/// Each rucksack holds at least one item, and any character other than whitespace is an item.
+fn rucksacks() -> impl Parser<char, (), Error = Simple<char>> {
+    let item = filter(|c: &char| !c.is_whitespace()).labelled("item");
+
+    lines(item.repeated().at_least(1)).ignored()
+}
+
+#[aoc_generator(day3)]
+fn parse(data: &str) -> Result<String, ParseFailure> {
+    let data = normalize(data);
+
+    parse_input(rucksacks(), &data, &input_name(3))?;
+    Ok(data.into_owned())
 }
 
 #[aoc(day3, part1)]
-fn part1(data: &str) -> u32 {
-    data.lines().map(|line| {
-        let (left, right) = line.split_at(line.len() / 2);
-        (bitmap(left) & bitmap(right)).trailing_zeros()
-    }).sum()
+fn part1(data: &str) -> Result<u32, RucksackError> {
+    priority_sum(&shared_by_compartments(data)?, priority)
 }
 
 #[aoc(day3, part2)]
-fn part2(data: &str) -> u32 {
-    let lines: Vec<_> = data.lines().collect();
-    lines.chunks_exact(3).map(|group| {
-        group.iter().map(bitmap).reduce(BitAnd::bitand).unwrap().trailing_zeros()
-    }).sum()
-}
\ No newline at end of file
+fn part2(data: &str) -> Result<u32, RucksackError> {
+    priority_sum(&shared_by_groups(data, 3)?, priority)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "\
+vJrwpWtwJgWrhcsFMMfFFhFp
+jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL
+PmmdzqPrVvPwwTWBwg
+wMqvLMZHhHMvwLHjbvcjnnSBnvTQFn
+ttgJtRGJQctTZtZT
+CrZsJsPPZsGzwwsLwLmpwMDw";
+
+    #[test]
+    fn groups_need_a_rucksack() {
+        assert_eq!(shared_by_groups("ab\nbc", 0), Err(RucksackError::EmptyGroups));
+        assert_eq!(shared_by_groups("ab\nbc", 2), Ok(vec!['b']));
+    }
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((part1(&parsed).unwrap(), part2(&parsed).unwrap()), (157, 70), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("vJrwpW\n\nttgJtR"), &[("Unexpected token, expected one of: item", 7..8)]);
+        assert_fails(parse("vJr wpW"), &[("Unexpected token, expected one of: end of input, \\n", 3..4)]);
+    }
+}
@@ -1,50 +1,90 @@
 use aoc_runner_derive::{aoc_generator, aoc};
-use parse_display::{FromStr, Display, ParseError};
+use chumsky::prelude::*;
 
+use crate::{interval::{Interval, IntervalSet}, parse::{lines, normalize, num, input_name, parse_input, ParseFailure}};
 
-#[derive(Display, FromStr)]
-#[display("{first},{second}")]
-struct Pair {
-    first: Range,
-    second: Range
-}
+/// The sections assigned to each elf in a group, one group per line.
+fn groups() -> impl Parser<char, Vec<Vec<Interval>>, Error = Simple<char>> {
+    let sections = num().then_ignore(just('-')).then(num())
+        .try_map(|(from, to), span| if from <= to {
+            Ok(Interval::inclusive(from, to))
+        } else {
+            Err(Simple::custom(span, format!("sections {}-{} run backwards", from, to)))
+        });
 
-#[derive(Display, FromStr)]
-#[display("{from}-{to}")]
-struct Range {
-    from: usize,
-    to: usize
+    lines(sections.separated_by(just(',')).at_least(1))
 }
 
 #[aoc_generator(day4)]
-fn parse(data: &str) -> Result<Vec<Pair>, ParseError> {
-    data.lines().map(str::parse).collect()
-}
-
-fn contains(first: &Range, second: &Range) -> bool {
-    first.from <= second.from && first.to >= second.to
-}
+fn parse(data: &str) -> Result<Vec<Vec<Interval>>, ParseFailure> {
+    let data = normalize(data);
 
-fn overlaps(first: &Range, second: &Range) -> bool {
-    first.from <= second.to && first.to >= second.from
+    parse_input(groups(), &data, &input_name(4))
 }
 
-type Op = fn(&Range, &Range) -> bool;
+/// The elves in `group` whose sections are all covered by the rest of the group.
+pub fn redundant(group: &[Interval]) -> Vec<usize> {
+    (0..group.len()).filter(|&i| {
+        let others: IntervalSet = group.iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(_, &interval)| interval)
+            .collect();
 
-fn either(pair: &Pair, op: Op) -> bool {
-    op(&pair.first, &pair.second) || op(&pair.second, &pair.first)
+        others.contains(&group[i])
+    }).collect()
 }
 
-fn count(data: &Vec<Pair>, op: Op) -> usize {
-    data.iter().filter(|p| either(p, op)).count()
+/// Whether any two elves in `group` share a section.
+pub fn any_overlap(group: &[Interval]) -> bool {
+    group.iter().enumerate().any(|(i, a)| group[i + 1..].iter().any(|b| a.overlaps(b)))
 }
 
 #[aoc(day4, part1)]
-fn part1(data: &Vec<Pair>) -> usize {
-    count(data, contains)
+fn part1(data: &[Vec<Interval>]) -> usize {
+    data.iter().filter(|group| !redundant(group).is_empty()).count()
 }
 
 #[aoc(day4, part2)]
-fn part2(data: &Vec<Pair>) -> usize {
-    count(data, overlaps)
-}
\ No newline at end of file
+fn part2(data: &[Vec<Interval>]) -> usize {
+    data.iter().filter(|group| any_overlap(group)).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "\
+2-4,6-8
+2-3,4-5
+5-7,7-9
+2-8,3-7
+6-6,4-6
+2-6,4-8";
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((part1(&parsed), part2(&parsed)), (2, 4), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn redundant_elves_may_be_covered_by_several_others() {
+        let group = |ranges: &[(i64, i64)]| ranges.iter().map(|&(first, last)| Interval::inclusive(first, last)).collect::<Vec<_>>();
+
+        assert_eq!(redundant(&group(&[(1, 3), (2, 5), (4, 6)])), [1]);
+        assert_eq!(redundant(&group(&[(2, 8), (3, 7), (4, 6)])), [1, 2]);
+        assert!(redundant(&group(&[(1, 2), (4, 5), (3, 3)])).is_empty());
+        assert_eq!(redundant(&group(&[(1, 4), (1, 4), (2, 2), (9, 9)])), [0, 1, 2]);
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("2-4,6-8\n5-3,1-2"), &[("sections 5-3 run backwards", 8..11)]);
+        assert_fails(parse("2-4,6-x"), &[("Unexpected token, expected one of: number", 6..7)]);
+    }
+}
@@ -1,83 +1,336 @@
+use std::{fmt, str::FromStr};
+
 use aoc_runner_derive::{aoc, aoc_generator};
-use parse_display::{FromStr, Display, ParseError};
+use chumsky::prelude::*;
+use parse_display::{FromStr, Display};
+
+use crate::parse::{lines, normalize, num, input_name, parse_input, ParseFailure};
 
-#[derive(Display, FromStr, Clone, Copy)]
+#[derive(Display, FromStr, Clone, Copy, Debug, PartialEq, Eq)]
 #[display("move {count} from {from} to {to}")]
-struct Move {
-    count: usize,
-    from: usize,
-    to: usize
+pub struct Move {
+    pub count: usize,
+    pub from: usize,
+    pub to: usize
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Model {
+    /// Moves crates one at a time.
+    CrateMover9000,
+    /// Moves several crates at once, keeping their order.
+    CrateMover9001,
+}
+
+#[derive(Display, Debug, PartialEq, Eq)]
+pub enum CraneError {
+    #[display("move {step}: there is no stack {stack}")]
+    NoSuchStack { step: usize, stack: usize },
+    #[display("move {step}: stack {stack} ran out, holding {available} crates but asked to move {count}")]
+    NotEnoughCrates { step: usize, stack: usize, available: usize, count: usize },
+}
+
+impl std::error::Error for CraneError {}
+
+/// Stacks of crates, numbered from 1, each listed from the bottom up.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stacks(pub Vec<Vec<char>>);
+
+impl Stacks {
+    /// The crate on top of each stack, with a space for each empty stack.
+    pub fn tops(&self) -> String {
+        self.0.iter().map(|stack| stack.last().copied().unwrap_or(' ')).collect()
+    }
+}
+
+impl FromStr for Stacks {
+    type Err = ParseFailure;
+
+    /// Reads the puzzle's drawing, by the same rules as the generator.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_input(drawing().then_ignore(end()), &normalize(s), "drawing")
+    }
+}
+
+impl fmt::Display for Stacks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let height = self.0.iter().map(Vec::len).max().unwrap_or(0);
+
+        for level in (0..height).rev() {
+            let row: Vec<String> = self.0.iter()
+                .map(|stack| stack.get(level).map_or("   ".to_string(), |c| format!("[{}]", c)))
+                .collect();
+            writeln!(f, "{}", row.join(" "))?;
+        }
+
+        let labels: Vec<String> = (1..=self.0.len()).map(|i| format!("{:^3}", i)).collect();
+        writeln!(f, "{}", labels.join(" "))
+    }
+}
+
+#[derive(Clone)]
+pub struct Crane {
+    model: Model,
+    stacks: Stacks,
+    step: usize,
 }
 
-fn transpose(data: Vec<Vec<char>>) -> Vec<Vec<char>> {
-    let len = data[0].len();
-    let mut result: Vec<_> = (0..len).map(|_| Vec::new()).collect();
+impl Crane {
+    pub fn new(model: Model, stacks: Stacks) -> Self {
+        Self { model, stacks, step: 0 }
+    }
+
+    pub fn stacks(&self) -> &Stacks {
+        &self.stacks
+    }
+
+    /// Carries out the next move. Moves are numbered from 1 in the order they are applied,
+    /// and a move that fails leaves the stacks untouched.
+    pub fn apply(&mut self, Move { count, from, to }: Move) -> Result<(), CraneError> {
+        let step = self.step + 1;
+
+        let stacks = &mut self.stacks.0;
+
+        for stack in [from, to] {
+            if stack == 0 || stack > stacks.len() {
+                return Err(CraneError::NoSuchStack { step, stack })
+            }
+        }
+
+        let available = stacks[from - 1].len();
+        if available < count {
+            return Err(CraneError::NotEnoughCrates { step, stack: from, available, count })
+        }
 
-    for line in data {
-        for (i, c) in line.into_iter().enumerate() {
-            if c == ' ' {
-                continue;
+        // Moving crates from a stack back onto itself leaves it as it was, whichever the model
+        if from != to {
+            let crates = stacks[from - 1].split_off(available - count);
+            match self.model {
+                Model::CrateMover9000 => stacks[to - 1].extend(crates.into_iter().rev()),
+                Model::CrateMover9001 => stacks[to - 1].extend(crates),
             }
-            result[i].push(c)
         }
+
+        self.step = step;
+        Ok(())
+    }
+
+    /// Applies `moves` one by one, yielding the stacks after each. Stops after the first
+    /// move that fails.
+    pub fn replay(self, moves: &[Move]) -> Replay<'_> {
+        Replay { crane: self, moves: moves.iter(), failed: false }
     }
+}
 
-    result
+pub struct Replay<'a> {
+    crane: Crane,
+    moves: std::slice::Iter<'a, Move>,
+    failed: bool,
 }
 
-fn parse_stacks(data: &str) -> Vec<Vec<char>> {
-    let rows: Vec<Vec<char>> = data.lines()
-        .rev()
-        .skip(1)
-        .map(|l| l.chars().skip(1).step_by(4).collect())
-        .collect();
+impl Iterator for Replay<'_> {
+    type Item = Result<Stacks, CraneError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None
+        }
 
-    transpose(rows)
+        let result = self.crane.apply(*self.moves.next()?);
+        self.failed = result.is_err();
+
+        Some(result.map(|()| self.crane.stacks.clone()))
+    }
 }
 
-#[aoc_generator(day5)]
-fn parse(data: &str) -> Result<(Vec<Vec<char>>, Vec<Move>), ParseError> {
-    let (stacks_data, moves_data) = data.split_once("\n\n").unwrap();
+/// The base line must number the stacks from 1, and rows may stop short of the last stack but
+/// not run past it. Every crate must rest on another crate or the floor.
+fn drawing() -> impl Parser<char, Stacks, Error = Simple<char>> {
+    let slot = just('[')
+        .ignore_then(filter(|c: &char| !c.is_whitespace()).labelled("crate"))
+        .then_ignore(just(']'))
+        .map(Some)
+        .or(just("   ").to(None))
+        .map_with_span(|slot, span| (slot, span));
+    let row = slot.separated_by(just(' ')).at_least(1).then_ignore(just('\n'));
+    let label = just(' ').repeated()
+        .ignore_then(num::<usize>())
+        .map_with_span(|label, span| (label, span));
 
-    let stacks = parse_stacks(stacks_data);
-    let moves = moves_data.lines().map(str::parse).collect::<Result<_, _>>()?;
+    row.repeated().then(label.repeated().at_least(1)).validate(|(rows, labels), _, emit| {
+        for (i, (label, span)) in labels.iter().enumerate() {
+            if *label != i + 1 {
+                emit(Simple::custom(span.clone(), format!("expected stack {}, found {}", i + 1, label)))
+            }
+        }
 
-    Ok((stacks, moves))
+        let mut stacks = vec![Vec::new(); labels.len()];
+        for (level, row) in rows.into_iter().rev().enumerate() {
+            for (i, (slot, span)) in row.into_iter().enumerate() {
+                let message = match (stacks.get_mut(i), slot) {
+                    (None, Some(_)) => format!("there are only {} stacks", labels.len()),
+                    (Some(stack), Some(c)) if stack.len() < level => format!("crate {} isn't resting on anything", c),
+                    (Some(stack), Some(c)) => {
+                        stack.push(c);
+                        continue
+                    },
+                    (_, None) => continue,
+                };
+
+                emit(Simple::custom(span, message))
+            }
+        }
+
+        Stacks(stacks)
+    })
+}
+
+/// The drawing, a blank line, then the moves.
+fn procedure() -> impl Parser<char, (Stacks, Vec<Move>), Error = Simple<char>> {
+    let step = just("move ").ignore_then(num())
+        .then_ignore(just(" from ")).then(num())
+        .then_ignore(just(" to ")).then(num())
+        .map(|((count, from), to)| Move { count, from, to });
+
+    drawing().then_ignore(just("\n\n")).then(lines(step))
+}
+
+#[aoc_generator(day5)]
+fn parse(data: &str) -> Result<(Stacks, Vec<Move>), ParseFailure> {
+    let data = normalize(data);
+
+    parse_input(procedure(), &data, &input_name(5))
 }
 
-fn create_string(stack: &Vec<Vec<char>>) -> String {
-    let mut result = String::new();
+fn run(model: Model, stacks: &Stacks, moves: &[Move]) -> Result<String, CraneError> {
+    let mut crane = Crane::new(model, stacks.clone());
 
-    for s in stack {
-        result.push(*s.last().unwrap());
+    for &m in moves {
+        crane.apply(m)?;
     }
 
-    result
+    Ok(crane.stacks().tops())
 }
 
 #[aoc(day5, part1)]
-fn part1((stack, moves): &(Vec<Vec<char>>, Vec<Move>)) -> String {
-    let mut stack = stack.clone();
+fn part1((stacks, moves): &(Stacks, Vec<Move>)) -> Result<String, CraneError> {
+    run(Model::CrateMover9000, stacks, moves)
+}
+
+#[aoc(day5, part2)]
+fn part2((stacks, moves): &(Stacks, Vec<Move>)) -> Result<String, CraneError> {
+    run(Model::CrateMover9001, stacks, moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = concat!(
+    "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 \n\n",
+    "move 1 from 2 to 1\nmove 3 from 1 to 3\nmove 2 from 2 to 1\nmove 1 from 1 to 2",
+);
+
+    fn round_trip(stacks: Stacks) {
+        assert_eq!(stacks.to_string().parse::<Stacks>().unwrap(), stacks);
+    }
+
+    #[test]
+    fn example_drawing_round_trips() {
+        let drawing = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 \n";
+        let stacks: Stacks = drawing.parse().unwrap();
+
+        assert_eq!(stacks, Stacks(vec![vec!['Z', 'N'], vec!['M', 'C', 'D'], vec!['P']]));
+        assert_eq!(stacks.to_string(), drawing);
+        round_trip(stacks);
+    }
 
-    for &Move { count, from, to } in moves {
-        for _ in 0..count {
-            let c = stack[from - 1].pop().unwrap();
-            stack[to - 1].push(c);
+    #[test]
+    fn ragged_drawings_round_trip() {
+        round_trip(Stacks(vec![vec![], vec!['A'], vec![]]));
+        round_trip(Stacks(vec![vec!['A', 'B', 'C', 'D'], vec![], vec!['E']]));
+        round_trip(Stacks(vec![vec![]; 4]));
+    }
+
+    #[test]
+    fn more_than_nine_stacks_round_trip() {
+        let stacks = Stacks((0..12).map(|i| {
+            (0..(i * 7) % 5).map(|level| (b'A' + ((i + level) % 26) as u8) as char).collect()
+        }).collect());
+
+        assert_eq!(stacks.0.iter().filter(|stack| stack.is_empty()).count(), 3);
+        round_trip(stacks);
+    }
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((part1(&parsed).unwrap(), part2(&parsed).unwrap()), ("CMZ".to_string(), "MCD".to_string()), "{:?}", data);
         }
     }
 
-    create_string(&stack)
-}
+    fn abc() -> Stacks {
+        Stacks(vec![vec!['A', 'B', 'C'], vec![]])
+    }
 
-#[aoc(day5, part2)]
-fn part2((stack, moves): &(Vec<Vec<char>>, Vec<Move>)) -> String {
-    let mut stack = stack.clone();
+    #[test]
+    fn moving_onto_the_same_stack_changes_nothing() {
+        for model in [Model::CrateMover9000, Model::CrateMover9001] {
+            let mut crane = Crane::new(model, abc());
 
-    for &Move { count, from, to } in moves {
-        let range = stack[from - 1].len() - count..;
-        let crates: Vec<_> = stack[from - 1].drain(range).collect();
-        stack[to - 1].extend(crates);
+            assert_eq!(crane.apply(Move { count: 3, from: 1, to: 1 }), Ok(()));
+            assert_eq!(crane.stacks(), &abc());
+        }
     }
 
-    create_string(&stack)
-}
\ No newline at end of file
+    #[test]
+    fn models_differ_in_crate_order() {
+        let mut crane = Crane::new(Model::CrateMover9000, abc());
+        crane.apply(Move { count: 2, from: 1, to: 2 }).unwrap();
+        assert_eq!(crane.stacks(), &Stacks(vec![vec!['A'], vec!['C', 'B']]));
+
+        let mut crane = Crane::new(Model::CrateMover9001, abc());
+        crane.apply(Move { count: 2, from: 1, to: 2 }).unwrap();
+        assert_eq!(crane.stacks(), &Stacks(vec![vec!['A'], vec!['B', 'C']]));
+    }
+
+    #[test]
+    fn failed_moves_are_numbered_and_leave_the_stacks() {
+        let mut crane = Crane::new(Model::CrateMover9000, abc());
+
+        assert_eq!(crane.apply(Move { count: 1, from: 3, to: 1 }), Err(CraneError::NoSuchStack { step: 1, stack: 3 }));
+        assert_eq!(crane.apply(Move { count: 1, from: 1, to: 0 }), Err(CraneError::NoSuchStack { step: 1, stack: 0 }));
+        assert_eq!(crane.apply(Move { count: 1, from: 1, to: 2 }), Ok(()));
+        assert_eq!(
+            crane.apply(Move { count: 3, from: 1, to: 2 }),
+            Err(CraneError::NotEnoughCrates { step: 2, stack: 1, available: 2, count: 3 }),
+        );
+        assert_eq!(crane.stacks(), &Stacks(vec![vec!['A', 'B'], vec!['C']]));
+    }
+
+    #[test]
+    fn replay_stops_after_the_first_failure() {
+        let moves = [
+            Move { count: 1, from: 1, to: 2 },
+            Move { count: 5, from: 1, to: 2 },
+            Move { count: 1, from: 1, to: 2 },
+        ];
+        let steps: Vec<_> = Crane::new(Model::CrateMover9000, abc()).replay(&moves).collect();
+
+        assert_eq!(steps, [
+            Ok(Stacks(vec![vec!['A', 'B'], vec!['C']])),
+            Err(CraneError::NotEnoughCrates { step: 2, stack: 1, available: 2, count: 5 }),
+        ]);
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("    [D]    \n[N]        \n[Z] [M] [P]\n 1   2   3 \n\nmove 1 from 2 to 1"), &[("crate D isn't resting on anything", 4..7)]);
+        assert_fails(parse("    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   3   2 \n\nmove 1 from 2 to 1"), &[("expected stack 2, found 3", 30..34), ("expected stack 3, found 2", 34..38)]);
+        assert_fails(parse("    [D]    \n[N] [C]    \n[Z] [M] [P] [Q]\n 1   2   3 \n\nmove 1 from 2 to 1"), &[("there are only 3 stacks", 28..31)]);
+        assert_fails(parse("[Z] [M]\n 1   2 \n\nmove 1 from 2 to x"), &[("Unexpected token, expected one of: number", 33..34)]);
+    }
+}
@@ -1,37 +1,239 @@
-use aoc_runner_derive::aoc;
+use std::io::{self, Read};
 
-fn bit(char: u8) -> u32 {
-    match char {
-        b'a'..=b'z' => 1 << (char - b'a'),
-        _ => panic!("outside range: {}", char)
-    }
+use aoc_runner_derive::{aoc, aoc_generator};
+use chumsky::prelude::*;
+
+use crate::parse::{normalize, input_name, parse_input, ParseFailure};
+
+/// Tracks the last `window` bytes of a stream and how many distinct values they hold.
+pub struct MarkerDetector {
+    window: Vec<u8>,
+    counts: [usize; 256],
+    distinct: usize,
+    seen: usize,
 }
 
-fn message_offset<const N: usize>(data: &[u8]) -> usize {
-    let mut bitset = 0u32;
+impl MarkerDetector {
+    pub fn new(window: usize) -> Self {
+        assert!(window > 0, "window must hold at least one byte");
 
-    for i in 0..N {
-        bitset ^= bit(data[i]);
+        Self {
+            window: vec![0; window],
+            counts: [0; 256],
+            distinct: 0,
+            seen: 0,
+        }
     }
 
-    for i in N..data.len() {
-        if bitset.count_ones() == N as u32 {
-            return i;
+    /// Feeds the next byte. Returns the number of bytes seen so far if the last `window` of
+    /// them are all different.
+    pub fn push(&mut self, byte: u8) -> Option<usize> {
+        let slot = self.seen % self.window.len();
+
+        if self.seen >= self.window.len() {
+            let old = self.window[slot] as usize;
+            self.counts[old] -= 1;
+            if self.counts[old] == 0 {
+                self.distinct -= 1;
+            }
+        }
+
+        self.window[slot] = byte;
+        self.counts[byte as usize] += 1;
+        if self.counts[byte as usize] == 1 {
+            self.distinct += 1;
         }
+        self.seen += 1;
+
+        (self.distinct == self.window.len()).then_some(self.seen)
+    }
+}
 
-        bitset ^= bit(data[i]);
-        bitset ^= bit(data[i - N]);
+/// Every offset in `reader` that ends a run of `window` distinct bytes, read a buffer at a
+/// time so memory use doesn't depend on the length of the stream.
+pub fn markers<R: Read>(reader: R, window: usize) -> Markers<R> {
+    Markers {
+        reader,
+        detector: MarkerDetector::new(window),
+        buffer: Box::new([0; 8192]),
+        pos: 0,
+        len: 0,
+        done: false,
     }
+}
 
-    panic!("no solution");
+pub struct Markers<R> {
+    reader: R,
+    detector: MarkerDetector,
+    buffer: Box<[u8; 8192]>,
+    pos: usize,
+    len: usize,
+    done: bool,
+}
+
+impl<R: Read> Iterator for Markers<R> {
+    type Item = io::Result<usize>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            while self.pos < self.len {
+                let byte = self.buffer[self.pos];
+                self.pos += 1;
+
+                if let Some(offset) = self.detector.push(byte) {
+                    return Some(Ok(offset))
+                }
+            }
+
+            match self.reader.read(&mut self.buffer[..]) {
+                Ok(0) => self.done = true,
+                Ok(len) => (self.pos, self.len) = (0, len),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e))
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// A single line of printable ASCII, so that every character is one byte of the stream.
+fn datastream() -> impl Parser<char, Vec<u8>, Error = Simple<char>> {
+    filter(char::is_ascii_graphic)
+        .labelled("printable ASCII")
+        .map(|c| c as u8)
+        .repeated()
+        .then_ignore(end())
+}
+
+#[aoc_generator(day6)]
+fn parse(data: &str) -> Result<Vec<u8>, ParseFailure> {
+    let data = normalize(data);
+
+    parse_input(datastream(), &data, &input_name(6))
 }
 
 #[aoc(day6, part1)]
-fn part1(data: &[u8]) -> usize {
-    message_offset::<4>(data)
+fn part1(data: &[u8]) -> Option<usize> {
+    markers(data, 4).next()?.ok()
 }
 
 #[aoc(day6, part2)]
-fn part2(data: &[u8]) -> usize {
-    message_offset::<14>(data)
-}
\ No newline at end of file
+fn part2(data: &[u8]) -> Option<usize> {
+    markers(data, 14).next()?.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "mjqjpqmgbljsphdztnvjfqwrcgsmlb";
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((part1(&parsed), part2(&parsed)), (Some(7), Some(19)), "{:?}", data);
+        }
+    }
+
+    /// Every offset ending `window` distinct bytes, checked the slow way.
+    fn brute_force(data: &[u8], window: usize) -> Vec<usize> {
+        data.windows(window)
+            .enumerate()
+            .filter(|(_, bytes)| bytes.iter().collect::<std::collections::HashSet<_>>().len() == window)
+            .map(|(i, _)| i + window)
+            .collect()
+    }
+
+    fn all_markers(reader: impl Read, window: usize) -> Vec<usize> {
+        markers(reader, window).collect::<io::Result<_>>().unwrap()
+    }
+
+    /// Hands out at most `chunk` bytes a read, interrupting every other read.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        chunk: usize,
+        interrupt: bool,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                return Err(io::ErrorKind::Interrupted.into())
+            }
+
+            let len = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn every_marker_is_found() {
+        assert_eq!(all_markers(&b"abcabd"[..], 3), [3, 4, 5, 6]);
+        assert_eq!(all_markers(&b"mjqjpqmgbljsphdztnvjfqwrcgsmlb"[..], 4), brute_force(b"mjqjpqmgbljsphdztnvjfqwrcgsmlb", 4));
+        assert_eq!(all_markers(&b"aaab"[..], 2), [4]);
+    }
+
+    #[test]
+    fn any_byte_can_be_in_a_marker() {
+        assert_eq!(all_markers(&[b'\n', 0xff, b'\n', 0, 0xff][..], 2), [2, 3, 4, 5]);
+        assert_eq!(all_markers(&[0, 0xff, b'\n', b'a'][..], 4), [4]);
+    }
+
+    #[test]
+    fn window_sizes_at_the_edges() {
+        assert_eq!(all_markers(&b"aab"[..], 1), [1, 2, 3]);
+        assert!(all_markers(&b"abc"[..], 5).is_empty());
+        assert!(all_markers(&b""[..], 1).is_empty());
+    }
+
+    #[test]
+    fn markers_span_buffer_boundaries() {
+        let mut data = vec![b'a'; 8190];
+        data.extend(b"bcd");
+        assert_eq!(all_markers(&data[..], 4), [8193]);
+
+        let mut state = 6u64;
+        let data: Vec<u8> = (0..20_000).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            b'a' + ((state >> 33) % 16) as u8
+        }).collect();
+
+        for window in [4, 8] {
+            let expected = brute_force(&data, window);
+
+            assert_eq!(all_markers(&data[..], window), expected);
+            assert_eq!(all_markers(Trickle { data: &data, chunk: 7, interrupt: false }, window), expected);
+        }
+    }
+
+    #[test]
+    fn read_errors_end_the_stream() {
+        struct Broken;
+
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::UnexpectedEof.into())
+            }
+        }
+
+        let mut markers = markers(Broken, 4);
+        assert_eq!(markers.next().map(|r| r.unwrap_err().kind()), Some(io::ErrorKind::UnexpectedEof));
+        assert!(markers.next().is_none());
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("mjqj\u{7}pqm"), &[("Unexpected token, expected one of: end of input", 4..5)]);
+        assert_fails(parse("mjqj pqm"), &[("Unexpected token, expected one of: end of input", 4..5)]);
+    }
+}
@@ -1,6 +1,9 @@
 use std::{collections::BTreeMap};
 
 use aoc_runner_derive::{aoc, aoc_generator};
+use chumsky::prelude::*;
+
+use crate::parse::{lines, normalize, num, input_name, parse_input, ParseFailure};
 
 const MAX_DIR_SIZE: u64 = 100000;
 const SPACE_AVAILABLE: u64 = 70000000;
@@ -13,18 +16,18 @@ struct Dir {
 }
 
 impl Dir {
-    pub fn insert(&mut self, path: &[&str], size: u64) {
+    pub fn insert(&mut self, path: &[String], size: u64) {
         self.size += size;
 
         if path.is_empty() {
             return;
         }
 
-        let dir = path[0];
+        let dir = &path[0];
         let path = &path[1..];
 
-        self.children.entry(dir.to_string())
-            .or_insert_with(Default::default)
+        self.children.entry(dir.clone())
+            .or_default()
             .insert(path, size);
     }
 
@@ -49,36 +52,52 @@ impl Dir {
     }
 }
 
+#[derive(Clone)]
+enum Line {
+    Cd(String),
+    Ls,
+    Dir,
+    File(u64),
+}
+
+/// The commands and their output, a line each.
+fn session() -> impl Parser<char, Vec<Line>, Error = Simple<char>> {
+    let name = filter(|c: &char| !c.is_whitespace())
+        .labelled("name")
+        .repeated()
+        .at_least(1)
+        .collect::<String>();
+
+    let line = choice((
+        just("$ cd ").ignore_then(name).map(Line::Cd),
+        just("$ ls").to(Line::Ls),
+        just("dir ").ignore_then(name).to(Line::Dir),
+        num().then_ignore(just(' ')).then_ignore(name).map(Line::File),
+    ));
+
+    lines(line)
+}
+
 #[aoc_generator(day7)]
-fn parse(data: &str) -> Dir {
+fn parse(data: &str) -> Result<Dir, ParseFailure> {
+    let data = normalize(data);
+
     let mut path = Vec::new();
     let mut dir = Dir::default();
 
-    for line in data.lines().skip(1) {
-        if line.starts_with("$") {
-            let command = line.strip_prefix("$ ").unwrap();
-            match &command[0..2] {
-                "cd" => {
-                    let new_path = &command[3..];
-                    if new_path == ".." {
-                        path.pop();
-                    } else {
-                        path.push(new_path);
-                    }
-                },
-                "ls" => continue,
-                cmd => panic!("unrecognized command {}", cmd)
-            }
-        } else if line.starts_with("dir") {
-            continue
-        } else {
-            let (size, _name) = line.split_once(' ').unwrap();
-            let size = size.parse::<u64>().unwrap();
-            dir.insert(&path, size);
+    for line in parse_input(session(), &data, &input_name(7))? {
+        match line {
+            Line::Cd(name) if name == "/" => path.clear(),
+            Line::Cd(name) if name == ".." => {
+                path.pop();
+            },
+            Line::Cd(name) => path.push(name),
+            Line::Ls | Line::Dir => {},
+            Line::File(size) => dir.insert(&path, size),
         }
     }
 
-    dir
+    Ok(dir)
 }
 
 #[aoc(day7, part1)]
@@ -89,4 +108,50 @@ fn part1(dir: &Dir) -> u64 {
 #[aoc(day7, part2)]
 fn part2(dir: &Dir) -> u64 {
     dir.find_smallest(dir.size - (SPACE_AVAILABLE - MIN_SPACE)).unwrap()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "\
+$ cd /
+$ ls
+dir a
+14848514 b.txt
+8504156 c.dat
+dir d
+$ cd a
+$ ls
+dir e
+29116 f
+2557 g
+62596 h.lst
+$ cd e
+$ ls
+584 i
+$ cd ..
+$ cd ..
+$ cd d
+$ ls
+4060174 j
+8033020 d.log
+5626152 d.ext
+7214296 k";
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((part1(&parsed), part2(&parsed)), (95437, 24933642), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("$ cd /\n$ rm a"), &[("Unexpected token, expected one of: c, l", 9..10)]);
+        assert_fails(parse("$ cd /\n$ ls\n12x b.txt"), &[("Unexpected token, expected one of:  ", 14..15)]);
+    }
+}
@@ -1,79 +1,271 @@
-use std::collections::HashSet;
-
 use aoc_runner_derive::{aoc, aoc_generator};
+use chumsky::prelude::*;
+
+use crate::parse::{lines, normalize, input_name, parse_input, ParseFailure};
+
+pub struct Forest {
+    width: usize,
+    height: usize,
+    heights: Vec<u8>,
+}
+
+impl Forest {
+    pub fn new(width: usize, height: usize, heights: Vec<u8>) -> Self {
+        assert_eq!(heights.len(), width * height);
+        Self { width, height, heights }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.heights[y * self.width + x]
+    }
+
+    fn row(&self, y: usize) -> &[u8] {
+        &self.heights[y * self.width..(y + 1) * self.width]
+    }
+
+    /// A pseudo-random forest, for benchmarking.
+    pub fn generate(width: usize, height: usize, seed: u64) -> Self {
+        let mut state = seed;
+        let heights = (0..width * height).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((state >> 33) % 10) as u8
+        }).collect();
+
+        Self::new(width, height, heights)
+    }
+}
+
+/// A fixed-size set of bits over the forest, one per tree.
+pub struct Bitset {
+    width: usize,
+    words: Vec<u64>,
+}
+
+impl Bitset {
+    fn new(width: usize, height: usize) -> Self {
+        Self { width, words: vec![0; (width * height).div_ceil(64)] }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        let i = y * self.width + x;
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn set(&mut self, x: usize, y: usize) {
+        let i = y * self.width + x;
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.words.len() * 8
+    }
+}
 
 #[aoc_generator(day8)]
-fn parse(data: &str) -> Vec<Vec<u8>> {
-    data.lines().map(|l| l.bytes().map(|c| c - b'0').collect()).collect()
+fn parse(data: &str) -> Result<Forest, ParseFailure> {
+    let data = normalize(data);
+
+    parse_input(forest(), &data, &input_name(8))
+}
+
+/// Rows of digits, each as wide as the first.
+fn forest() -> impl Parser<char, Forest, Error = Simple<char>> {
+    let row = filter(char::is_ascii_digit)
+        .labelled("digit")
+        .map(|c| c as u8 - b'0')
+        .repeated()
+        .at_least(1)
+        .map_with_span(|row, span| (row, span));
+
+    lines(row).validate(|rows, _, emit| {
+        let (width, height) = (rows.first().map_or(0, |(row, _)| row.len()), rows.len());
+        let mut heights = Vec::with_capacity(width * height);
+
+        for (mut row, span) in rows {
+            if row.len() != width {
+                emit(Simple::custom(span, format!("row has {} trees, expected {}", row.len(), width)))
+            }
+            row.resize(width, 0);
+            heights.extend(row);
+        }
+
+        Forest::new(width, height, heights)
+    })
 }
 
-fn in_range(data: &Vec<Vec<u8>>, x: i32, y: i32) -> bool {
-    x >= 0 && y >= 0 && x < data[0].len() as i32 && y < data.len() as i32
+/// Which trees can be seen from outside the forest. Rows are swept from both ends, and
+/// columns are swept a row at a time while tracking the tallest tree seen in each column,
+/// so the heights are only ever read in order.
+pub fn visibility(forest: &Forest) -> Bitset {
+    let mut visible = Bitset::new(forest.width, forest.height);
+
+    // Heights are stored plus one, so that zero means no tree has been seen yet
+    let mut tallest = vec![0u8; forest.width];
+    for y in 0..forest.height {
+        sweep_columns(forest, &mut visible, &mut tallest, y);
+    }
+
+    tallest.fill(0);
+    for y in (0..forest.height).rev() {
+        sweep_columns(forest, &mut visible, &mut tallest, y);
+    }
+
+    for y in 0..forest.height {
+        let row = forest.row(y);
+        sweep_row(&mut visible, y, row.iter().copied().enumerate());
+        sweep_row(&mut visible, y, row.iter().copied().enumerate().rev());
+    }
+
+    visible
 }
 
-fn visible_from_edge(data: &Vec<Vec<u8>>, visible: &mut HashSet<(i32, i32)>, (mut x, mut y): (i32, i32), (dx, dy): (i32, i32)) {
-    visible.insert((x, y));
-    let mut max = 0;
-    while in_range(data, x, y) {
-        let height = data[y as usize][x as usize];
-        if height > max {
-            max = height;
-            visible.insert((x, y));
+fn sweep_columns(forest: &Forest, visible: &mut Bitset, tallest: &mut [u8], y: usize) {
+    for (x, (&height, tallest)) in forest.row(y).iter().zip(tallest).enumerate() {
+        if height + 1 > *tallest {
+            *tallest = height + 1;
+            visible.set(x, y);
         }
-        x += dx;
-        y += dy;
     }
 }
 
-fn visible_from_tree(data: &Vec<Vec<u8>>, scores: &mut Vec<Vec<u64>>, (mut x, mut y): (i32, i32), (dx, dy): (i32, i32)) {
+fn sweep_row(visible: &mut Bitset, y: usize, trees: impl Iterator<Item=(usize, u8)>) {
+    let mut tallest = 0;
+
+    for (x, height) in trees {
+        if height + 1 > tallest {
+            tallest = height + 1;
+            visible.set(x, y);
+
+            if height == 9 {
+                break
+            }
+        }
+    }
+}
+
+fn visible_from_tree(forest: &Forest, scores: &mut [u64], trees: impl Iterator<Item=(usize, usize)>) {
     let mut stack = vec![(10, 0)]; // (height, index)
-    let mut i = 0;
 
-    while in_range(data, x, y) {
-        let height = data[y as usize][x as usize];
+    for (i, (x, y)) in trees.enumerate() {
+        let height = forest.get(x, y);
 
         while stack.last().unwrap().0 < height {
             stack.pop();
         }
 
-        scores[y as usize][x as usize] *= i - stack.last().unwrap().1;
+        scores[y * forest.width + x] *= (i - stack.last().unwrap().1) as u64;
         stack.push((height, i));
-
-        i += 1;
-        x += dx;
-        y += dy;
     }
 }
 
-fn each_direction<T>(data: &Vec<Vec<u8>>, result: &mut T, mut func: impl FnMut(&Vec<Vec<u8>>, &mut T, (i32, i32), (i32, i32))) {
-    let width = data[0].len();
-    let height = data.len();
+/// The scenic score of every tree, in row-major order.
+pub fn scenic_scores(forest: &Forest) -> Vec<u64> {
+    let (width, height) = (forest.width, forest.height);
+    let mut scores = vec![1; width * height];
 
     for x in 0..width {
-        func(data, result, (x as i32, 0), (0, 1));
-        func(data, result, (x as i32, height as i32 - 1), (0, -1));
+        visible_from_tree(forest, &mut scores, (0..height).map(|y| (x, y)));
+        visible_from_tree(forest, &mut scores, (0..height).rev().map(|y| (x, y)));
     }
 
     for y in 0..height {
-        func(data, result, (0, y as i32), (1, 0));
-        func(data, result, (width as i32 - 1, y as i32), (-1, 0));
+        visible_from_tree(forest, &mut scores, (0..width).map(|x| (x, y)));
+        visible_from_tree(forest, &mut scores, (0..width).rev().map(|x| (x, y)));
     }
+
+    scores
 }
 
-#[aoc(day8, part1)]
-fn part1(data: &Vec<Vec<u8>>) -> usize {
-    let mut visible = HashSet::new();
+/// The coordinates and score of the tree with the highest scenic score.
+pub fn best_tree(forest: &Forest, scores: &[u64]) -> ((usize, usize), u64) {
+    scores.iter()
+        .enumerate()
+        .max_by_key(|&(_, score)| score)
+        .map(|(i, &score)| ((i % forest.width, i / forest.width), score))
+        .unwrap()
+}
+
+/// Prints the forest with visible trees in bright green and hidden ones dimmed. The tree at
+/// `best` is shown in bright red.
+pub fn render(forest: &Forest, visible: &Bitset, best: (usize, usize)) -> String {
+    let mut out = String::new();
 
-    each_direction(data, &mut visible, visible_from_edge);
+    for y in 0..forest.height {
+        for x in 0..forest.width {
+            let colour = if (x, y) == best {
+                "\u{001b}[31;1m" // bright red
+            } else if visible.get(x, y) {
+                "\u{001b}[32;1m" // bright green
+            } else {
+                "\u{001b}[2m" // dim
+            };
 
-    visible.len()
+            out += &format!("{}{}\u{001b}[0m", colour, forest.get(x, y));
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+#[aoc(day8, part1)]
+fn part1(forest: &Forest) -> usize {
+    visibility(forest).count()
 }
 
 #[aoc(day8, part2)]
-fn part2(data: &Vec<Vec<u8>>) -> u64 {
-    let mut scores = vec![vec![1; data[0].len()]; data.len()];
+fn part2(forest: &Forest) -> u64 {
+    best_tree(forest, &scenic_scores(forest)).1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "30373\n25512\n65332\n33549\n35390";
 
-    each_direction(data, &mut scores, visible_from_tree);
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
 
-    scores.iter().flatten().copied().max().unwrap()
-}
\ No newline at end of file
+            assert_eq!((part1(&parsed), part2(&parsed)), (21, 8), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("30373\n2551\n65332"), &[("row has 4 trees, expected 5", 6..10)]);
+        assert_fails(parse("30373\n25x12\n65332"), &[("Unexpected token, expected one of: end of input, \\n", 8..9)]);
+    }
+
+    #[test]
+    fn example_visibility_and_best_tree() {
+        let forest = parse(EXAMPLE).unwrap();
+        let visible = visibility(&forest);
+        let mask: Vec<String> = (0..forest.height()).map(|y| {
+            (0..forest.width()).map(|x| if visible.get(x, y) { '#' } else { '.' }).collect()
+        }).collect();
+
+        assert_eq!(mask, ["#####", "###.#", "##.##", "#.#.#", "#####"]);
+        assert_eq!(visible.count(), 21);
+        assert_eq!(best_tree(&forest, &scenic_scores(&forest)), ((2, 3), 8));
+
+        let (red, green, dim, reset) = ("\u{001b}[31;1m", "\u{001b}[32;1m", "\u{001b}[2m", "\u{001b}[0m");
+        let row = format!("{green}3{reset}{dim}3{reset}{red}5{reset}{dim}4{reset}{green}9{reset}");
+        assert_eq!(render(&forest, &visible, (2, 3)).lines().nth(3), Some(row.as_str()));
+    }
+}
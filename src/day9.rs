@@ -1,61 +1,253 @@
 use std::collections::HashSet;
 
 use aoc_runner_derive::{aoc, aoc_generator};
-use parse_display::{FromStr, Display, ParseError};
+use chumsky::prelude::*;
+use parse_display::{FromStr, Display};
+
+use crate::parse::{lines, normalize, num, input_name, parse_input, ParseFailure};
 
 #[derive(FromStr, Display)]
 #[display("{direction} {steps}")]
-struct Move {
-    direction: Direction,
-    steps: i32
+pub struct Move {
+    pub direction: Direction,
+    pub steps: i32
 }
 
-#[derive(FromStr, Display)]
-enum Direction {
+#[derive(FromStr, Display, Clone, Copy)]
+pub enum Direction {
     U, D, L, R
 }
 
+fn moves() -> impl Parser<char, Vec<Move>, Error = Simple<char>> {
+    let direction = choice((
+        just('U').to(Direction::U),
+        just('D').to(Direction::D),
+        just('L').to(Direction::L),
+        just('R').to(Direction::R),
+    ));
+    let step = direction.then_ignore(just(' ')).then(num())
+        .map(|(direction, steps)| Move { direction, steps });
+
+    lines(step)
+}
+
 #[aoc_generator(day9)]
-fn parse(data: &str) -> Result<Vec<Move>, ParseError> {
-    data.lines().map(str::parse).collect()
+fn parse(data: &str) -> Result<Vec<Move>, ParseFailure> {
+    let data = normalize(data);
+
+    parse_input(moves(), &data, &input_name(9))
 }
 
 #[aoc(day9, part1)]
-fn part1(data: &Vec<Move>) -> usize {
-    simulate_rope::<2>(data)
+fn part1(data: &[Move]) -> usize {
+    simulate(data, 2).visited().len()
 }
 
 #[aoc(day9, part2)]
-fn part2(data: &Vec<Move>) -> usize {
-    simulate_rope::<10>(data)
+fn part2(data: &[Move]) -> usize {
+    simulate(data, 10).visited().len()
 }
 
-fn simulate_rope<const N: usize>(data: &Vec<Move>) -> usize {
-    let mut visited = HashSet::new();
-    let mut rope = [(0i32, 0i32); N];
+pub fn simulate(data: &[Move], len: usize) -> Rope {
+    let mut rope = Rope::new(len);
 
     for m in data {
         for _ in 0..m.steps {
-            match m.direction {
-                Direction::U => rope[0].1 -= 1,
-                Direction::D => rope[0].1 += 1,
-                Direction::L => rope[0].0 -= 1,
-                Direction::R => rope[0].0 += 1,
+            rope.step(&m.direction);
+        }
+    }
+
+    rope
+}
+
+pub struct Rope {
+    knots: Vec<(i32, i32)>,
+    visited: HashSet<(i32, i32)>,
+}
+
+impl Rope {
+    pub fn new(len: usize) -> Self {
+        assert!(len > 0, "a rope needs at least one knot");
+
+        Self {
+            knots: vec![(0, 0); len],
+            visited: HashSet::from([(0, 0)]),
+        }
+    }
+
+    pub fn knots(&self) -> &[(i32, i32)] {
+        &self.knots
+    }
+
+    pub fn visited(&self) -> &HashSet<(i32, i32)> {
+        &self.visited
+    }
+
+    /// Moves the head one step and drags the rest of the rope after it, returning the new
+    /// knot positions.
+    pub fn step(&mut self, direction: &Direction) -> &[(i32, i32)] {
+        let head = &mut self.knots[0];
+        match direction {
+            Direction::U => head.1 -= 1,
+            Direction::D => head.1 += 1,
+            Direction::L => head.0 -= 1,
+            Direction::R => head.0 += 1,
+        }
+
+        for i in 1..self.knots.len() {
+            let (head, tail) = (self.knots[i - 1], &mut self.knots[i]);
+            let (dx, dy) = (head.0 - tail.0, head.1 - tail.1);
+
+            if dx.abs() > 1 || dy.abs() > 1 {
+                tail.0 += dx.signum();
+                tail.1 += dy.signum();
             }
+        }
+
+        self.visited.insert(self.knots[self.knots.len() - 1]);
 
-            for i in 0..N - 1 {
-                let (head, tail) = (rope[i], &mut rope[i + 1]);
-                let (dx, dy) = (head.0 - tail.0, head.1 - tail.1);
+        self.knots()
+    }
 
-                if dx.abs() > 1 || dy.abs() > 1 {
-                    tail.0 += dx.signum();
-                    tail.1 += dy.signum();
-                }
+    /// Draws the cells visited by the tail as `#`, with the start marked `s`. If `show_knots`
+    /// is set, the knots are drawn on top as `H` followed by their index, as in the puzzle. Knots
+    /// 10 to 35 are labelled `a` to `z`, and any further knots `*`.
+    pub fn render(&self, show_knots: bool) -> String {
+        let points = self.visited.iter().chain(&self.knots);
+        let (min_x, max_x) = points.clone().map(|p| p.0).fold((0, 0), |(lo, hi), x| (lo.min(x), hi.max(x)));
+        let (min_y, max_y) = points.map(|p| p.1).fold((0, 0), |(lo, hi), y| (lo.min(y), hi.max(y)));
+
+        let mut out = String::new();
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                let knot = self.knots.iter().position(|&k| k == (x, y)).filter(|_| show_knots);
+
+                out.push(match knot {
+                    Some(0) => 'H',
+                    Some(i) => char::from_digit(i as u32, 36).unwrap_or('*'),
+                    None if (x, y) == (0, 0) => 's',
+                    None if self.visited.contains(&(x, y)) => '#',
+                    None => '.',
+                });
             }
+            out.push('\n');
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{assert_fails, variants};
+
+    const EXAMPLE: &str = "R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2";
+
+    #[test]
+    fn example_parses_with_any_line_endings() {
+        for data in variants(EXAMPLE) {
+            let parsed = parse(&data).unwrap();
+
+            assert_eq!((part1(&parsed), part2(&parsed)), (13, 1), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn broken_input_is_reported() {
+        assert_fails(parse("R 4\nX 2"), &[("Unexpected token, expected one of: D, L, R, U", 4..5)]);
+        assert_fails(parse("R 4\nU x"), &[("Unexpected token, expected one of: number", 6..7)]);
+    }
+
+    const LARGER_EXAMPLE: &str = "R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20";
+
+    /// The cells visited by the tail in the larger example as the puzzle draws them, trimmed to
+    /// the area `render` covers.
+    const LARGER_VISITED: &str = "\
+......................
+......................
+......................
+......................
+......................
+......................
+......................
+......................
+......................
+#.....................
+#.............###.....
+#............#...#....
+.#..........#.....#...
+..#..........#.....#..
+...#........#.......#.
+....#......s.........#
+.....#..............#.
+......#............#..
+.......#..........#...
+........#........#....
+.........########.....
+";
+
+    /// The same, with the final knot positions drawn on top.
+    const LARGER_KNOTS: &str = "\
+H.....................
+1.....................
+2.....................
+3.....................
+4.....................
+5.....................
+6.....................
+7.....................
+8.....................
+9.....................
+#.............###.....
+#............#...#....
+.#..........#.....#...
+..#..........#.....#..
+...#........#.......#.
+....#......s.........#
+.....#..............#.
+......#............#..
+.......#..........#...
+........#........#....
+.........########.....
+";
 
-            visited.insert(rope[N - 1]);
+    #[test]
+    fn knots_follow_the_puzzle() {
+        let mut rope = Rope::new(10);
+        for _ in 0..4 {
+            rope.step(&Direction::R);
         }
+        assert_eq!(rope.knots(), [(4, 0), (3, 0), (2, 0), (1, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0), (0, 0)]);
+
+        let rope = simulate(&parse(EXAMPLE).unwrap(), 2);
+        assert_eq!(rope.knots(), [(2, -2), (1, -2)]);
+
+        let rope = simulate(&parse(LARGER_EXAMPLE).unwrap(), 10);
+        assert_eq!(rope.knots(), (0..10).map(|i| (-11, i - 15)).collect::<Vec<_>>());
     }
 
-    visited.len()
-}
\ No newline at end of file
+    #[test]
+    fn larger_example_matches_the_puzzle_map() {
+        let rope = simulate(&parse(LARGER_EXAMPLE).unwrap(), 10);
+        let expected: HashSet<_> = LARGER_VISITED.lines().zip(-15..).flat_map(|(row, y)| {
+            row.chars().zip(-11..).filter(|&(c, _)| c == '#' || c == 's').map(move |(_, x)| (x, y))
+        }).collect();
+
+        assert_eq!(rope.visited().len(), 36);
+        assert_eq!(rope.visited(), &expected);
+        assert_eq!(rope.render(false), LARGER_VISITED);
+        assert_eq!(rope.render(true), LARGER_KNOTS);
+    }
+
+    #[test]
+    fn long_ropes_are_labelled_in_base_36() {
+        let mut rope = Rope::new(37);
+        for _ in 0..36 {
+            rope.step(&Direction::R);
+        }
+
+        assert_eq!(rope.render(true), "*zyxwvutsrqponmlkjihgfedcba987654321H\n");
+        assert_eq!(rope.render(false), "s....................................\n");
+    }
+}
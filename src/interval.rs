@@ -0,0 +1,272 @@
+use std::ops::{Range, RangeInclusive};
+
+/// A half-open range of integers, `start..end`. Intervals with `end <= start` are empty.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Interval {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Interval {
+    pub const EMPTY: Interval = Interval { start: 0, end: 0 };
+
+    pub fn exclusive(start: i64, end: i64) -> Self {
+        Self { start, end }
+    }
+
+    pub fn inclusive(first: i64, last: i64) -> Self {
+        Self { start: first, end: last + 1 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.end <= self.start
+    }
+
+    pub fn len(&self) -> u64 {
+        (self.end - self.start).max(0) as u64
+    }
+
+    pub fn contains_point(&self, point: i64) -> bool {
+        self.start <= point && point < self.end
+    }
+
+    /// Whether every point of `other` is in this interval. The empty interval is contained
+    /// by everything.
+    pub fn contains(&self, other: &Interval) -> bool {
+        other.is_empty() || (self.start <= other.start && other.end <= self.end)
+    }
+
+    pub fn overlaps(&self, other: &Interval) -> bool {
+        !self.intersection(other).is_empty()
+    }
+
+    pub fn intersection(&self, other: &Interval) -> Interval {
+        Interval { start: self.start.max(other.start), end: self.end.min(other.end) }
+    }
+
+    /// The points of this interval outside `other`, as up to two pieces.
+    pub fn difference(&self, other: &Interval) -> impl Iterator<Item=Interval> {
+        let pieces = if other.is_empty() {
+            [*self, Interval::EMPTY]
+        } else {
+            [
+                Interval { start: self.start, end: self.end.min(other.start) },
+                Interval { start: self.start.max(other.end), end: self.end },
+            ]
+        };
+
+        pieces.into_iter().filter(|piece| !piece.is_empty())
+    }
+
+    /// Splits into two halves, the first of which is no longer than the second.
+    pub fn split(&self) -> (Interval, Interval) {
+        let mid = self.start + (self.end - self.start) / 2;
+        (Interval { start: self.start, end: mid }, Interval { start: mid, end: self.end })
+    }
+}
+
+impl From<Range<i64>> for Interval {
+    fn from(range: Range<i64>) -> Self {
+        Self::exclusive(range.start, range.end)
+    }
+}
+
+impl From<RangeInclusive<i64>> for Interval {
+    fn from(range: RangeInclusive<i64>) -> Self {
+        Self::inclusive(*range.start(), *range.end())
+    }
+}
+
+/// A set of integers, kept as sorted intervals that neither overlap nor touch.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntervalSet {
+    intervals: Vec<Interval>,
+}
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&Interval> {
+        self.intervals.iter()
+    }
+
+    /// The number of integers in the set.
+    pub fn len(&self) -> u64 {
+        self.intervals.iter().map(Interval::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    pub fn insert(&mut self, interval: Interval) {
+        if interval.is_empty() {
+            return
+        }
+
+        // Everything overlapping or touching `interval` is merged into it
+        let first = self.intervals.partition_point(|i| i.end < interval.start);
+        let last = self.intervals.partition_point(|i| i.start <= interval.end);
+
+        let merged = self.intervals[first..last].iter().fold(interval, |acc, i| {
+            Interval { start: acc.start.min(i.start), end: acc.end.max(i.end) }
+        });
+
+        self.intervals.splice(first..last, [merged]);
+    }
+
+    pub fn remove(&mut self, interval: Interval) {
+        if interval.is_empty() {
+            return
+        }
+
+        let first = self.intervals.partition_point(|i| i.end <= interval.start);
+        let last = self.intervals.partition_point(|i| i.start < interval.end);
+
+        let pieces: Vec<Interval> = self.intervals[first..last].iter()
+            .flat_map(|i| i.difference(&interval))
+            .collect();
+
+        self.intervals.splice(first..last, pieces);
+    }
+
+    pub fn contains_point(&self, point: i64) -> bool {
+        self.intervals.iter().any(|i| i.contains_point(point))
+    }
+
+    /// Whether every point of `interval` is in the set.
+    pub fn contains(&self, interval: &Interval) -> bool {
+        interval.is_empty() || self.intervals.iter().any(|i| i.contains(interval))
+    }
+
+    pub fn overlaps(&self, interval: &Interval) -> bool {
+        self.intervals.iter().any(|i| i.overlaps(interval))
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        let mut result = self.clone();
+        result.extend(other.iter().copied());
+        result
+    }
+
+    pub fn intersection(&self, other: &IntervalSet) -> IntervalSet {
+        self.iter()
+            .flat_map(|a| other.iter().map(move |b| a.intersection(b)))
+            .collect()
+    }
+
+    pub fn difference(&self, other: &IntervalSet) -> IntervalSet {
+        let mut result = self.clone();
+        for &interval in other.iter() {
+            result.remove(interval);
+        }
+        result
+    }
+}
+
+impl Extend<Interval> for IntervalSet {
+    fn extend<T: IntoIterator<Item=Interval>>(&mut self, iter: T) {
+        for interval in iter {
+            self.insert(interval);
+        }
+    }
+}
+
+impl FromIterator<Interval> for IntervalSet {
+    fn from_iter<T: IntoIterator<Item=Interval>>(iter: T) -> Self {
+        let mut set = IntervalSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A set of `start..end` intervals, as `(start, end)` pairs.
+    fn set(intervals: &[(i64, i64)]) -> IntervalSet {
+        intervals.iter().map(|&(start, end)| Interval::exclusive(start, end)).collect()
+    }
+
+    fn intervals(set: &IntervalSet) -> Vec<(i64, i64)> {
+        set.iter().map(|i| (i.start, i.end)).collect()
+    }
+
+    #[test]
+    fn constructors_agree() {
+        assert_eq!(Interval::inclusive(2, 4), Interval::exclusive(2, 5));
+        assert_eq!(Interval::from(2..=4), Interval::from(2..5));
+        assert_eq!(Interval::inclusive(2, 4).len(), 3);
+        assert_eq!(Interval::inclusive(3, 3).len(), 1);
+        assert!(Interval::inclusive(3, 2).is_empty());
+        assert_eq!(Interval::exclusive(5, 1).len(), 0);
+    }
+
+    #[test]
+    fn empty_intervals_are_contained_by_everything() {
+        let empty = Interval::exclusive(9, 3);
+
+        assert!(Interval::inclusive(1, 2).contains(&empty));
+        assert!(Interval::EMPTY.contains(&empty));
+        assert!(IntervalSet::new().contains(&empty));
+        assert!(!empty.contains(&Interval::inclusive(5, 5)));
+        assert!(!empty.contains_point(5));
+        assert!(!empty.overlaps(&Interval::exclusive(0, 10)));
+    }
+
+    #[test]
+    fn insert_merges_touching_and_overlapping_intervals() {
+        assert_eq!(intervals(&set(&[(1, 3), (3, 5)])), [(1, 5)]);
+        assert_eq!(intervals(&set(&[(1, 4), (2, 6), (8, 9)])), [(1, 6), (8, 9)]);
+        assert_eq!(intervals(&set(&[(10, 12), (1, 2), (5, 6), (2, 5)])), [(1, 6), (10, 12)]);
+        assert_eq!(intervals(&set(&[(1, 3), (4, 6)])), [(1, 3), (4, 6)]);
+        assert_eq!(intervals(&set(&[(3, 5), (0, 10)])), [(0, 10)]);
+        assert!(intervals(&set(&[(4, 2)])).is_empty());
+        assert_eq!(set(&[(1, 3), (3, 5)]).len(), 4);
+    }
+
+    #[test]
+    fn remove_splits_intervals() {
+        let mut set = set(&[(0, 10), (20, 30)]);
+
+        set.remove(Interval::exclusive(3, 5));
+        assert_eq!(intervals(&set), [(0, 3), (5, 10), (20, 30)]);
+
+        set.remove(Interval::exclusive(8, 22));
+        assert_eq!(intervals(&set), [(0, 3), (5, 8), (22, 30)]);
+
+        set.remove(Interval::exclusive(-5, 3));
+        set.remove(Interval::EMPTY);
+        assert_eq!(intervals(&set), [(5, 8), (22, 30)]);
+        assert!(!set.contains_point(4) && set.contains_point(5) && !set.contains_point(8));
+    }
+
+    #[test]
+    fn interval_difference_has_up_to_two_pieces() {
+        let interval = Interval::exclusive(0, 10);
+        let difference = |(start, end)| interval.difference(&Interval::exclusive(start, end)).map(|i| (i.start, i.end)).collect::<Vec<_>>();
+
+        assert_eq!(difference((3, 5)), [(0, 3), (5, 10)]);
+        assert_eq!(difference((-3, 5)), [(5, 10)]);
+        assert_eq!(difference((5, 20)), [(0, 5)]);
+        assert!(difference((-1, 11)).is_empty());
+        assert_eq!(difference((20, 30)), [(0, 10)]);
+        assert_eq!(difference((5, 5)), [(0, 10)]);
+    }
+
+    #[test]
+    fn set_operations() {
+        let (a, b) = (set(&[(0, 5), (10, 15)]), set(&[(3, 12)]));
+
+        assert_eq!(intervals(&a.union(&b)), [(0, 15)]);
+        assert_eq!(intervals(&a.intersection(&b)), [(3, 5), (10, 12)]);
+        assert_eq!(intervals(&a.difference(&b)), [(0, 3), (12, 15)]);
+        assert_eq!(intervals(&b.difference(&a)), [(5, 10)]);
+        assert!(a.intersection(&set(&[(5, 10)])).is_empty());
+        assert!(a.contains(&Interval::exclusive(1, 4)) && !a.contains(&Interval::exclusive(4, 11)));
+        assert!(a.overlaps(&Interval::exclusive(4, 11)) && !a.overlaps(&Interval::exclusive(5, 10)));
+    }
+}
@@ -1,19 +1,21 @@
 use aoc_runner_derive::aoc_lib;
 
-mod parse;
-mod day1;
-mod day2;
-mod day3;
-mod day4;
-mod day5;
-mod day6;
+pub mod parse;
+pub mod biguint;
+pub mod interval;
+pub mod day1;
+pub mod day2;
+pub mod day3;
+pub mod day4;
+pub mod day5;
+pub mod day6;
 mod day7;
-mod day8;
-mod day9;
-mod day10;
-mod day11;
-mod day12;
-mod day13;
+pub mod day8;
+pub mod day9;
+pub mod day10;
+pub mod day11;
+pub mod day12;
+pub mod day13;
 mod day14;
 mod day15;
 mod day16;
@@ -1,92 +1,390 @@
-use ariadne::{Report, ReportKind, Fmt, Color, Label, Source};
+use std::{borrow::Cow, fmt, ops::Range, str::FromStr};
+
+use ariadne::{Report, ReportKind, Fmt, Color, Config, Label, Source};
 use chumsky::prelude::*;
 use lazy_static::lazy_static;
+use parse_display::Display;
 use regex::Regex;
+use serde_json::json;
+
+/// Puzzle input as every generator expects it: no byte order mark, `\n` line endings, no
+/// trailing whitespace on any line and no newline after the last one. Input that is already
+/// in that form is borrowed rather than copied.
+pub fn normalize(input: &str) -> Cow<'_, str> {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    let trimmed = input.trim_end();
+
+    let is_normal = trimmed.split('\n').all(|line| line.len() == line.trim_end().len());
+    if is_normal {
+        return Cow::Borrowed(trimmed)
+    }
+
+    let lines: Vec<&str> = trimmed.lines().map(str::trim_end).collect();
+    Cow::Owned(lines.join("\n"))
+}
+
+/// Everything wrong with one input, along with the input itself so that each error can be
+/// shown in context. Reports name the input by the file name given to [`parse_input`].
+pub struct ParseFailure {
+    name: String,
+    source: String,
+    errors: Vec<Simple<char>>,
+}
+
+impl ParseFailure {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn errors(&self) -> &[Simple<char>] {
+        &self.errors
+    }
+
+    /// Every error's report, one after another, with or without ANSI colours.
+    pub fn render(&self, colour: bool) -> String {
+        let mut out = Vec::new();
+        for e in &self.errors {
+            // Writing to a Vec can't fail
+            let _ = self.report(e, colour).write((self.name.as_str(), Source::from(&self.source)), &mut out);
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    pub fn eprint(&self) {
+        eprint!("{}", self.render(true))
+    }
+
+    /// A JSON array with an object per error. Spans count characters, and lines and columns
+    /// count from 1.
+    pub fn to_json(&self) -> String {
+        let errors: Vec<_> = self.errors.iter().map(|e| {
+            let (line, column) = self.position(e.span().start);
+
+            json!({
+                "file": self.name,
+                "message": message(e, false),
+                "line": line,
+                "column": column,
+                "span": { "start": e.span().start, "end": e.span().end },
+                "expected": expected(e).into_iter().map(|expected| expected.map(String::from)).collect::<Vec<_>>(),
+                "found": e.found().map(|&c| String::from(c)),
+            })
+        }).collect();
+
+        serde_json::Value::from(errors).to_string()
+    }
+
+    fn position(&self, offset: usize) -> (usize, usize) {
+        self.source.chars().take(offset).fold((1, 1), |(line, column), c| match c {
+            '\n' => (line + 1, 1),
+            _ => (line, column + 1),
+        })
+    }
+
+    fn report(&self, e: &Simple<char>, colour: bool) -> Report<(&str, Range<usize>)> {
+        let name = self.name.as_str();
+        let fg = |color| Some(color).filter(|_| colour);
+
+        // Labels are coloured in the source listing whatever the config says
+        let label = |span, message: String, color| {
+            let label = Label::new((name, span)).with_message(message);
+            if colour { label.with_color(color) } else { label }
+        };
+
+        let report = Report::build(ReportKind::Error, name, e.span().start)
+            .with_config(Config::default().with_color(colour))
+            .with_message(message(e, colour));
 
-fn print_errors_and_exit(errors: Vec<Simple<char>>, input: &str, day: usize) -> ! {
-    let name = format!("day{}.txt", day);
-    for e in errors {
-        let report = Report::build(ReportKind::Error, &name, e.span().start);
         let report = match e.reason() {
             chumsky::error::SimpleReason::Unclosed { span, delimiter } => report
-                .with_message(format!(
-                    "Unclosed delimiter {}",
-                    delimiter.fg(Color::Yellow)
+                .with_label(label(
+                    span.clone(),
+                    format!("Unclosed delimiter {}", delimiter.fg(fg(Color::Yellow))),
+                    Color::Yellow,
                 ))
-                .with_label(
-                    Label::new((&name, span.clone()))
-                        .with_message(format!(
-                            "Unclosed delimiter {}",
-                            delimiter.fg(Color::Yellow)
-                        ))
-                        .with_color(Color::Yellow),
-                )
-                .with_label(
-                    Label::new((&name, e.span()))
-                        .with_message(format!(
-                            "Must be closed before this: {}",
-                            e.found().map(|c| c.to_string())
-                                .unwrap_or("end of file".to_string())
-                                .fg(Color::Red)
-                        ))
-                        .with_color(Color::Red),
-                ),
+                .with_label(label(
+                    e.span(),
+                    format!(
+                        "Must be closed before this: {}",
+                        e.found().map(|c| c.to_string())
+                            .unwrap_or("end of file".to_string())
+                            .fg(fg(Color::Red))
+                    ),
+                    Color::Red,
+                )),
             chumsky::error::SimpleReason::Unexpected => report
-                .with_message(format!(
-                    "{}, expected one of: {}",
-                    if e.found().is_some() {
-                        "Unexpected token"
-                    } else {
-                        "Unexpected end of input"
-                    },
-                    if e.expected().len() == 0 {
-                        "something else".to_string()
-                    } else {
-                        e.expected()
-                            .map(|expected| match expected {
-                                Some(expected) => expected.escape_default().fg(Color::Green).to_string(),
-                                None => "end of input".fg(Color::Green).to_string(),
-                            })
-                            .collect::<Vec<_>>()
-                            .join(", ")
-                    }
-                ))
-                .with_label(
-                    Label::new((&name, e.span()))
-                        .with_message(format!(
-                            "Unexpected token: {}",
-                            e.found().map(|c| c.escape_default().to_string())
-                                .unwrap_or("end of file".to_string())
-                                .fg(Color::Red)
-                        ))
-                        .with_color(Color::Red),
-                ),
-            chumsky::error::SimpleReason::Custom(msg) => report.with_message(msg).with_label(
-                Label::new((&name, e.span()))
-                    .with_message(format!("{}", msg.fg(Color::Red)))
-                    .with_color(Color::Red),
-            ),
+                .with_label(label(
+                    e.span(),
+                    format!(
+                        "Unexpected token: {}",
+                        e.found().map(|c| c.escape_default().to_string())
+                            .unwrap_or("end of file".to_string())
+                            .fg(fg(Color::Red))
+                    ),
+                    Color::Red,
+                )),
+            chumsky::error::SimpleReason::Custom(msg) => report
+                .with_label(label(e.span(), format!("{}", msg.fg(fg(Color::Red))), Color::Red)),
         };
 
-        report.finish().eprint((&name, Source::from(input))).unwrap()
+        report.finish()
+    }
+}
+
+/// What `e` expected, in a stable order with the end of input first.
+fn expected(e: &Simple<char>) -> Vec<Option<char>> {
+    let mut expected: Vec<_> = e.expected().copied().collect();
+    expected.sort();
+    expected
+}
+
+fn message(e: &Simple<char>, colour: bool) -> String {
+    let fg = |color| Some(color).filter(|_| colour);
+
+    match e.reason() {
+        chumsky::error::SimpleReason::Unclosed { delimiter, .. } => format!(
+            "Unclosed delimiter {}",
+            delimiter.fg(fg(Color::Yellow))
+        ),
+        chumsky::error::SimpleReason::Unexpected => format!(
+            "{}, expected one of: {}",
+            if e.found().is_some() {
+                "Unexpected token"
+            } else {
+                "Unexpected end of input"
+            },
+            if let Some(label) = e.label() {
+                label.fg(fg(Color::Green)).to_string()
+            } else if e.expected().len() == 0 {
+                "something else".fg(fg(Color::Green)).to_string()
+            } else {
+                expected(e).into_iter()
+                    .map(|expected| match expected {
+                        Some(expected) => expected.escape_default().fg(fg(Color::Green)).to_string(),
+                        None => "end of input".fg(fg(Color::Green)).to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            }
+        ),
+        chumsky::error::SimpleReason::Custom(msg) => msg.clone(),
     }
+}
 
-    std::process::exit(1)
+impl fmt::Display for ParseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
 }
 
-pub fn parse_input<T>(parser: impl Parser<char, T, Error = Simple<char>>, input: &str, day: usize) -> T {
-    match parser.parse(input) {
-        Ok(result) => result,
-        Err(errors) => print_errors_and_exit(errors, input, day)
+/// The plain reports, since aoc-runner shows a failed generator's error with `{:#?}`.
+impl fmt::Debug for ParseFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(false))
     }
 }
 
+impl std::error::Error for ParseFailure {}
+
+/// The path aoc-runner reads `day`'s input from, relative to the crate, to name it by in
+/// reports.
+pub fn input_name(day: u32) -> String {
+    format!("input/{}/day{}.txt", crate::YEAR, day)
+}
+
+/// Runs `parser` over `input`, naming it `name` in any reports. For puzzle input that should
+/// be its [`input_name`].
+pub fn parse_input<T>(parser: impl Parser<char, T, Error = Simple<char>>, input: &str, name: &str) -> Result<T, ParseFailure> {
+    parser.parse(input).map_err(|errors| ParseFailure {
+        name: name.to_string(),
+        source: input.to_string(),
+        errors,
+    })
+}
+
+/// A base 10 integer with an optional `-`, reported at its span if it doesn't fit in `T`.
+pub fn num<T: FromStr>() -> impl Parser<char, T, Error = Simple<char>> + Clone {
+    just('-').or_not()
+        .chain::<char, _, _>(text::digits(10))
+        .collect::<String>()
+        .labelled("number")
+        .try_map(|s, span| s.parse().map_err(|_| Simple::custom(span, format!("{} is out of range", s))))
+}
+
+/// One `line` per line of [`normalize`]d input, and nothing after the last.
+pub fn lines<T>(line: impl Parser<char, T, Error = Simple<char>>) -> impl Parser<char, Vec<T>, Error = Simple<char>> {
+    line.separated_by(just('\n')).then_ignore(end())
+}
+
 lazy_static! {
     static ref INTS_REGEX: Regex = Regex::new(r"-?\d+").unwrap();
+    static ref UINTS_REGEX: Regex = Regex::new(r"\d+").unwrap();
+}
+
+/// Lines count from 1; spans are byte offsets into the text that was searched.
+#[derive(Display, Debug, Clone, PartialEq, Eq)]
+pub enum NumsError {
+    #[display("line {line}: {text} at {span:?} is out of range")]
+    OutOfRange { line: usize, span: Range<usize>, text: String },
+    #[display("line {line}: expected {expected} numbers at {span:?}, found {found}")]
+    WrongCount { line: usize, span: Range<usize>, expected: usize, found: usize },
 }
 
-pub fn ints(data: &str) -> Vec<i64> {
-    INTS_REGEX.find_iter(data)
-        .map(|m| m.as_str().parse().unwrap())
-        .collect()
-}
\ No newline at end of file
+impl std::error::Error for NumsError {}
+
+/// Every integer in `data`, in order. A `-` is only read as a sign when `T` can hold a
+/// negative number, so `2-4` is `[2, -4]` as `i64`s but `[2, 4]` as `u64`s.
+pub fn nums<T: FromStr>(data: &str) -> Result<Vec<T>, NumsError> {
+    scan(data, 1, 0)
+}
+
+pub fn ints(data: &str) -> Result<Vec<i64>, NumsError> {
+    nums(data)
+}
+
+pub fn uints(data: &str) -> Result<Vec<u64>, NumsError> {
+    nums(data)
+}
+
+/// Exactly `N` integers from `data`.
+pub fn nums_n<T: FromStr, const N: usize>(data: &str) -> Result<[T; N], NumsError> {
+    scan_n(data, 1, 0)
+}
+
+pub fn ints_n<const N: usize>(data: &str) -> Result<[i64; N], NumsError> {
+    nums_n(data)
+}
+
+/// Exactly `N` integers from every line of `data`, which may end in `\n` or `\r\n`.
+pub fn nums_lines<T: FromStr, const N: usize>(data: &str) -> Result<Vec<[T; N]>, NumsError> {
+    let mut offset = 0;
+
+    data.split_inclusive('\n').enumerate().map(|(index, line)| {
+        let nums = scan_n(line.trim_end_matches(['\r', '\n']), index + 1, offset);
+        offset += line.len();
+        nums
+    }).collect()
+}
+
+pub fn int_lines<const N: usize>(data: &str) -> Result<Vec<[i64; N]>, NumsError> {
+    nums_lines(data)
+}
+
+fn scan<T: FromStr>(data: &str, first_line: usize, offset: usize) -> Result<Vec<T>, NumsError> {
+    let regex = if "-1".parse::<T>().is_ok() { &*INTS_REGEX } else { &*UINTS_REGEX };
+
+    let mut line = first_line;
+    let mut counted = 0;
+
+    regex.find_iter(data).map(|m| {
+        line += data[counted..m.start()].matches('\n').count();
+        counted = m.start();
+
+        m.as_str().parse().map_err(|_| NumsError::OutOfRange {
+            line,
+            span: offset + m.start()..offset + m.end(),
+            text: m.as_str().to_string(),
+        })
+    }).collect()
+}
+
+fn scan_n<T: FromStr, const N: usize>(data: &str, line: usize, offset: usize) -> Result<[T; N], NumsError> {
+    let nums = scan(data, line, offset)?;
+    let found = nums.len();
+
+    nums.try_into().map_err(|_| NumsError::WrongCount {
+        line,
+        span: offset..offset + data.len(),
+        expected: N,
+        found,
+    })
+}
+
+/// `sample` with CRLF endings, a byte order mark, and with no or extra final newlines, as
+/// well as unchanged.
+#[cfg(test)]
+pub(crate) fn variants(sample: &str) -> Vec<String> {
+    let sample = sample.trim_end_matches('\n');
+    let crlf = sample.replace('\n', "\r\n");
+
+    vec![
+        sample.to_string(),
+        format!("{}\n", sample),
+        format!("{}\n\n\n", sample),
+        format!("{}\r\n", crlf),
+        format!("\u{feff}{}\n", sample),
+        format!("\u{feff}{}\r\n\r\n", crlf),
+    ]
+}
+
+/// Asserts that `result` failed with exactly the `expected` plain messages, at their spans.
+#[cfg(test)]
+pub(crate) fn assert_fails<T>(result: Result<T, ParseFailure>, expected: &[(&str, Range<usize>)]) {
+    let Err(failure) = result else { panic!("input parsed") };
+    let errors: Vec<_> = failure.errors.iter().map(|e| (message(e, false), e.span())).collect();
+    let errors: Vec<_> = errors.iter().map(|(message, span)| (message.as_str(), span.clone())).collect();
+
+    assert_eq!(errors, expected);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failure() -> ParseFailure {
+        parse_input(lines(num::<u8>()), "1\n2x\n3", "numbers.txt").unwrap_err()
+    }
+
+    #[test]
+    fn input_name_matches_the_runner() {
+        assert_eq!(input_name(7), "input/2022/day7.txt");
+    }
+
+    #[test]
+    fn signs_depend_on_the_type() {
+        assert_eq!(ints("2-4"), Ok(vec![2, -4]));
+        assert_eq!(uints("2-4"), Ok(vec![2, 4]));
+        assert_eq!(nums::<i8>("x=-5, y=7"), Ok(vec![-5, 7]));
+    }
+
+    #[test]
+    fn out_of_range_numbers_are_located() {
+        assert_eq!(nums::<u8>("1\n2 300"), Err(NumsError::OutOfRange { line: 2, span: 4..7, text: "300".to_string() }));
+        assert_eq!(uints("99999999999999999999"), Err(NumsError::OutOfRange { line: 1, span: 0..20, text: "99999999999999999999".to_string() }));
+    }
+
+    #[test]
+    fn wrong_counts_are_located() {
+        assert_eq!(ints_n::<3>("1,2,3"), Ok([1, 2, 3]));
+        assert_eq!(ints_n::<3>("1,2"), Err(NumsError::WrongCount { line: 1, span: 0..3, expected: 3, found: 2 }));
+        assert_eq!(int_lines::<2>("1 2\n3 4 5"), Err(NumsError::WrongCount { line: 2, span: 4..9, expected: 2, found: 3 }));
+    }
+
+    #[test]
+    fn line_offsets_count_crlf_endings() {
+        assert_eq!(int_lines::<2>("1 2\r\n3 -4\r\n"), Ok(vec![[1, 2], [3, -4]]));
+        assert_eq!(nums_lines::<u8, 2>("1 2\r\n3 999\r\n"), Err(NumsError::OutOfRange { line: 2, span: 7..10, text: "999".to_string() }));
+        assert_eq!(nums_lines::<u8, 2>("1 2\r\n3 4\r\n5\r\n"), Err(NumsError::WrongCount { line: 3, span: 10..11, expected: 2, found: 1 }));
+    }
+
+    #[test]
+    fn plain_reports_have_no_colour() {
+        let failure = failure();
+
+        assert!(failure.render(false).contains("numbers.txt:2:2"));
+        assert!(!failure.render(false).contains('\x1b'));
+        assert!(failure.render(true).contains('\x1b'));
+    }
+
+    #[test]
+    fn json_locates_each_error() {
+        let errors: serde_json::Value = serde_json::from_str(&failure().to_json()).unwrap();
+
+        assert_eq!(errors.as_array().map(Vec::len), Some(1));
+        assert_eq!(errors[0]["file"], "numbers.txt");
+        assert_eq!(errors[0]["line"], 2);
+        assert_eq!(errors[0]["column"], 2);
+        assert_eq!(errors[0]["span"], json!({ "start": 3, "end": 4 }));
+        assert_eq!(errors[0]["found"], "x");
+    }
+}